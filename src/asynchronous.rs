@@ -0,0 +1,103 @@
+//! Async acquisition of locks, using the tokio runtime.
+//!
+//! These functions never block a worker thread while waiting for the lock.
+//! They are cancellation safe: when the future is dropped, no lock file is left behind.
+//!
+//! Locks configured with [`LockOptions`] are acquired with [`LockOptions::lock_async`],
+//! [`LockOptions::lock_timeout_async`] and [`LockOptions::lock_until_async`].
+
+use std::{path::Path, time::Duration};
+
+use tokio::time::{self, Instant};
+
+use crate::{
+    wait::{self, Backoff},
+    Lock, LockName, LockOptions, LockResultWithDrop, Result,
+};
+
+/// Acquire the lock, waiting for the current holder to release it.
+pub async fn lock<N: LockName>(name: N) -> Result<Lock> {
+    LockOptions::new().lock_async(name).await
+}
+
+/// Acquire the lock, waiting at most `timeout` for the current holder to release it.
+/// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held after the timeout.
+pub async fn lock_timeout<N: LockName>(name: N, timeout: Duration) -> Result<LockResultWithDrop> {
+    LockOptions::new().lock_timeout_async(name, timeout).await
+}
+
+/// Acquire the lock, waiting until `deadline` for the current holder to release it.
+/// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held at the deadline.
+pub async fn lock_until<N: LockName>(name: N, deadline: Instant) -> Result<LockResultWithDrop> {
+    LockOptions::new().lock_until_async(name, deadline).await
+}
+
+impl LockOptions {
+    /// Acquire the lock, waiting for the current holder to release it without blocking the runtime.
+    /// See [`crate::asynchronous`].
+    pub async fn lock_async<N: LockName>(&self, name: N) -> Result<Lock> {
+        let path = self.path(name)?;
+        let lock = wait_for_lock(self, &path, None).await?;
+        Ok(lock.expect("waiting without deadline always return a lock"))
+    }
+
+    /// Acquire the lock, waiting at most `timeout` for the current holder to release it without blocking the runtime.
+    /// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held after the timeout.
+    pub async fn lock_timeout_async<N: LockName>(
+        &self,
+        name: N,
+        timeout: Duration,
+    ) -> Result<LockResultWithDrop> {
+        let path = self.path(name)?;
+        // the clock of the runtime can be paused
+        let deadline = wait::deadline_after(Instant::now().into_std(), timeout);
+        self.lock_path_until_async(&path, deadline.map(Instant::from_std))
+            .await
+    }
+
+    /// Acquire the lock, waiting until `deadline` for the current holder to release it without blocking the runtime.
+    /// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held at the deadline.
+    pub async fn lock_until_async<N: LockName>(
+        &self,
+        name: N,
+        deadline: Instant,
+    ) -> Result<LockResultWithDrop> {
+        let path = self.path(name)?;
+        self.lock_path_until_async(&path, Some(deadline)).await
+    }
+
+    async fn lock_path_until_async(
+        &self,
+        path: &Path,
+        deadline: Option<Instant>,
+    ) -> Result<LockResultWithDrop> {
+        let res = match wait_for_lock(self, path, deadline).await? {
+            Some(lock) => LockResultWithDrop::Locked(lock),
+            None => LockResultWithDrop::AlreadyLocked,
+        };
+        Ok(res)
+    }
+}
+
+async fn wait_for_lock(
+    options: &LockOptions,
+    path: &Path,
+    deadline: Option<Instant>,
+) -> Result<Option<Lock>> {
+    let mut backoff = Backoff::new(options.wait_strategy);
+
+    loop {
+        // the lock is taken synchronously and returned without any await point in between,
+        // so dropping the future can't leak it
+        if let LockResultWithDrop::Locked(lock) = options.try_lock_path(path)? {
+            return Ok(Some(lock));
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(None);
+        }
+
+        let delay = backoff.next_delay(deadline.map(Instant::into_std));
+        time::sleep(delay).await;
+    }
+}
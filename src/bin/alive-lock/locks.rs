@@ -0,0 +1,228 @@
+use std::{
+    path::Path,
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+use alive_lock_file::{
+    LockEntry, LockInfo, LockMode, LockOptions, LockState, RemoveResult, Result,
+};
+use clap::Args;
+use serde_json::{json, Value};
+
+/// List the lock files of the lock directory, their holder and whether it is running
+#[derive(Args)]
+pub struct ListArgs {
+    /// Print the locks as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Show the holder of a lock
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Print the lock as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Name of the lock, or absolute path of the lock file
+    name: String,
+}
+
+/// Remove the lock files left by processes that are not running anymore
+#[derive(Args)]
+pub struct GcArgs {
+    /// Print the removed locks as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn list(options: &LockOptions, args: ListArgs) -> Result<ExitCode> {
+    let entries = options.list_locks()?;
+
+    if args.json {
+        let entries = entries.iter().map(entry_json).collect();
+        println!("{:#}", Value::Array(entries));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut rows = vec![[
+        "NAME".to_owned(),
+        "STATE".to_owned(),
+        "PID".to_owned(),
+        "AGE".to_owned(),
+        "COMMAND".to_owned(),
+    ]];
+
+    for entry in &entries {
+        let info = state_info(&entry.state);
+        rows.push([
+            entry.name.clone(),
+            state_name(&entry.state).to_owned(),
+            info.map_or("-".to_owned(), |info| info.pid.to_string()),
+            info.map_or("-".to_owned(), |info| format_age(info.acquired_at)),
+            info.map_or("-".to_owned(), |info| info.args.join(" ")),
+        ]);
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for [name, state, pid, age, command] in rows {
+        println!(
+            "{name:<0$}  {state:<1$}  {pid:<2$}  {age:<3$}  {command}",
+            widths[0], widths[1], widths[2], widths[3]
+        );
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn info(options: &LockOptions, args: InfoArgs) -> Result<ExitCode> {
+    let path = super::lock_name(options, &args.name)?;
+    let state = options.lock_state(&path)?;
+
+    if args.json {
+        println!("{:#}", lock_json(&args.name, path.as_path(), &state));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("name:        {}", args.name);
+    println!("path:        {}", path.as_path().display());
+    println!("state:       {}", state_name(&state));
+
+    let Some(info) = state_info(&state) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_owned());
+
+    println!("pid:         {}", info.pid);
+    println!("mode:        {}", mode_name(info.mode));
+    println!("hostname:    {}", or_unknown(info.hostname.clone()));
+    println!(
+        "uid:         {}",
+        or_unknown(info.uid.map(|uid| uid.to_string()))
+    );
+    println!("acquired:    {} ago", format_age(info.acquired_at));
+    println!("command:     {}", info.args.join(" "));
+    println!(
+        "exe:         {}",
+        or_unknown(info.exe.as_ref().map(|exe| exe.display().to_string()))
+    );
+    println!("boot id:     {}", or_unknown(info.boot_id.clone()));
+    if let Some(ttl) = info.lease_ttl {
+        println!("lease ttl:   {}s", ttl.as_secs_f64());
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn gc(options: &LockOptions, args: GcArgs) -> Result<ExitCode> {
+    let mut removed = Vec::new();
+
+    for entry in options.list_locks()? {
+        if !matches!(entry.state, LockState::Stale(_)) {
+            continue;
+        }
+
+        // the lock could have been acquired again since it was listed
+        let path = super::lock_path(entry.path.clone())?;
+        if let RemoveResult::Removed(_) = options.remove_lock(&path)? {
+            removed.push(entry);
+        }
+    }
+
+    if args.json {
+        let removed = removed.iter().map(entry_json).collect();
+        println!("{:#}", Value::Array(removed));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for entry in &removed {
+        match state_info(&entry.state) {
+            Some(info) => println!("removed {} (pid {})", entry.name, info.pid),
+            None => println!("removed {}", entry.name),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn state_name(state: &LockState) -> &'static str {
+    match state {
+        LockState::Free => "free",
+        LockState::Held(_) => "held",
+        LockState::Stale(_) => "stale",
+        LockState::Unknown => "unknown",
+    }
+}
+
+fn state_info(state: &LockState) -> Option<&LockInfo> {
+    match state {
+        LockState::Held(info) | LockState::Stale(info) => Some(info),
+        LockState::Free | LockState::Unknown => None,
+    }
+}
+
+fn mode_name(mode: LockMode) -> &'static str {
+    match mode {
+        LockMode::Shared => "shared",
+        LockMode::Exclusive => "exclusive",
+    }
+}
+
+fn age(acquired_at: SystemTime) -> Duration {
+    acquired_at.elapsed().unwrap_or_default()
+}
+
+/// Format the age of a lock with its largest unit, like `42s` or `3h`.
+fn format_age(acquired_at: SystemTime) -> String {
+    let secs = age(acquired_at).as_secs();
+
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn entry_json(entry: &LockEntry) -> Value {
+    lock_json(&entry.name, &entry.path, &entry.state)
+}
+
+fn lock_json(name: &str, path: &Path, state: &LockState) -> Value {
+    let mut value = json!({
+        "name": name,
+        "path": path,
+        "state": state_name(state),
+    });
+
+    if let Some(info) = state_info(state) {
+        let acquired_at = info
+            .acquired_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        value["holder"] = json!({
+            "pid": info.pid,
+            "start_time": info.start_time,
+            "mode": mode_name(info.mode),
+            "hostname": info.hostname,
+            "uid": info.uid,
+            "acquired_at": acquired_at.as_secs_f64(),
+            "age": age(info.acquired_at).as_secs_f64(),
+            "exe": info.exe,
+            "args": info.args,
+            "boot_id": info.boot_id,
+            "lease_ttl": info.lease_ttl.map(|ttl| ttl.as_secs_f64()),
+        });
+    }
+
+    value
+}
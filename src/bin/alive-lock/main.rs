@@ -0,0 +1,77 @@
+//! Command line interface to the locks of the `alive_lock_file` crate,
+//! so shell scripts can share locks with the programs using it.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use alive_lock_file::{AbsolutePath, LockOptions, Result};
+use clap::{Parser, Subcommand};
+
+mod locks;
+mod run;
+
+/// Exit code when the command fail, distinct from the default exit code on contention.
+const ERROR_EXIT_CODE: u8 = 2;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Directory of the lock files [default: the runtime directory]
+    #[arg(long, global = true, value_name = "DIR")]
+    dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    Run(run::RunArgs),
+    List(locks::ListArgs),
+    Info(locks::InfoArgs),
+    Gc(locks::GcArgs),
+}
+
+fn main() -> ExitCode {
+    // exit quietly when the output is piped to a command like `head`, instead of panicking
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+
+    let cli = Cli::parse();
+
+    let mut options = LockOptions::new();
+    if let Some(dir) = &cli.dir {
+        options.dir(dir);
+    }
+
+    let res = match cli.command {
+        Command::Run(args) => run::run(&options, args),
+        Command::List(args) => locks::list(&options, args),
+        Command::Info(args) => locks::info(&options, args),
+        Command::Gc(args) => locks::gc(&options, args),
+    };
+
+    res.unwrap_or_else(|e| {
+        eprintln!("alive-lock: {e}");
+        ExitCode::from(ERROR_EXIT_CODE)
+    })
+}
+
+/// Resolve a lock name given on the command line.
+///
+/// Names starting with `/` are absolute paths, like lock names of the previous versions of this crate.
+fn lock_name(options: &LockOptions, name: &str) -> Result<AbsolutePath> {
+    if name.starts_with('/') {
+        AbsolutePath::new(name)
+    } else {
+        lock_path(options.path(name)?)
+    }
+}
+
+/// Path of a lock file of the lock directory.
+fn lock_path(path: PathBuf) -> Result<AbsolutePath> {
+    // the directory given on the command line can be relative
+    let path = std::path::absolute(&path).unwrap_or(path);
+    AbsolutePath::new(path)
+}
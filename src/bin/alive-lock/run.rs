@@ -0,0 +1,176 @@
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::{
+    ffi::OsString,
+    io::ErrorKind,
+    process::{Child, Command, ExitCode, ExitStatus},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use alive_lock_file::Backend;
+use alive_lock_file::{LockMode, LockOptions, LockResultWithDrop, Result};
+use clap::Args;
+
+/// Run a command while holding a lock, and release it when the command exit
+///
+/// Exit with the exit code of the command, or with the conflict exit code if the lock is held.
+/// On unix, the command inherit the `flock` of the lock: it stay held until the command and its children exit,
+/// even if alive-lock is killed.
+#[derive(Args)]
+pub struct RunArgs {
+    /// Fail immediately if the lock is held, instead of waiting for it
+    #[arg(short, long, conflicts_with = "timeout")]
+    nonblock: bool,
+
+    /// Fail if the lock is still held after this number of seconds
+    #[arg(short = 'w', long, value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// Exit code when the lock can't be acquired
+    #[arg(short = 'E', long, value_name = "CODE", default_value_t = 1)]
+    conflict_exit_code: u8,
+
+    /// Acquire the lock in shared mode
+    #[arg(short, long)]
+    shared: bool,
+
+    /// Name of the lock, or absolute path of the lock file
+    name: String,
+
+    /// Command to run, and its arguments
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<OsString>,
+}
+
+pub fn run(options: &LockOptions, args: RunArgs) -> Result<ExitCode> {
+    let mut options = options.clone();
+    if args.shared {
+        options.mode(LockMode::Shared);
+    }
+    #[cfg(unix)]
+    options.backend(Backend::Flock);
+
+    let name = super::lock_name(&options, &args.name)?;
+
+    let res = if args.nonblock {
+        options.try_lock(&name)?
+    } else if let Some(timeout) = args.timeout {
+        options.lock_timeout(&name, timeout)?
+    } else {
+        LockResultWithDrop::Locked(options.lock(&name)?)
+    };
+
+    let LockResultWithDrop::Locked(lock) = res else {
+        return Ok(ExitCode::from(args.conflict_exit_code));
+    };
+
+    let (program, program_args) = args.command.split_first().expect("the command is required");
+
+    let mut command = Command::new(program);
+    command.args(program_args);
+    #[cfg(unix)]
+    if let Some(fd) = lock.flock_fd() {
+        inherit_fd(&mut command, fd.as_raw_fd());
+    }
+
+    let code = match command.spawn() {
+        Ok(child) => exit_code(wait(child)),
+        Err(e) => {
+            eprintln!("alive-lock: can't run {}: {e}", program.to_string_lossy());
+            // like shells
+            if e.kind() == ErrorKind::NotFound {
+                127
+            } else {
+                126
+            }
+        }
+    };
+
+    lock.release()?;
+
+    Ok(ExitCode::from(code))
+}
+
+/// Let the child inherit this file descriptor, which is opened with `O_CLOEXEC` like every file of the standard library.
+#[cfg(unix)]
+fn inherit_fd(command: &mut Command, fd: std::os::fd::RawFd) {
+    use std::os::unix::process::CommandExt;
+
+    let pre_exec = move || {
+        // only async signal safe functions can be called between fork and exec
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    unsafe { command.pre_exec(pre_exec) };
+}
+
+/// Wait for the child to exit.
+///
+/// Like `system(3)`, `SIGINT` and `SIGQUIT` are ignored meanwhile, since the terminal also send them to the child.
+/// `SIGTERM` and `SIGHUP` are forwarded to the child.
+/// So the lock is released only after the child exit.
+#[cfg(unix)]
+fn wait(mut child: Child) -> std::io::Result<ExitStatus> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM},
+        iterator::Signals,
+    };
+
+    let mut signals = Signals::new([SIGINT, SIGQUIT, SIGTERM, SIGHUP])?;
+    let handle = signals.handle();
+    let pid = child.id() as libc::pid_t;
+
+    let forward = std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGTERM || signal == SIGHUP {
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+    });
+
+    let status = child.wait();
+
+    handle.close();
+    let _ = forward.join();
+
+    status
+}
+
+#[cfg(not(unix))]
+fn wait(mut child: Child) -> std::io::Result<ExitStatus> {
+    child.wait()
+}
+
+/// Exit code of a command, computed like shells for commands killed by a signal.
+fn exit_code(status: std::io::Result<ExitStatus>) -> u8 {
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("alive-lock: can't wait for the command: {e}");
+            return super::ERROR_EXIT_CODE;
+        }
+    };
+
+    if let Some(code) = status.code() {
+        return code as u8;
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal as u8;
+    }
+
+    super::ERROR_EXIT_CODE
+}
+
+fn parse_seconds(value: &str) -> std::result::Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds: {value}"))
+}
@@ -0,0 +1,64 @@
+use std::{env, path::PathBuf};
+
+use crate::Result;
+
+/// Directory in which lock files are created by default.
+///
+/// The first available directory of this list is used:
+/// 1. `$XDG_RUNTIME_DIR`, which is set for user sessions.
+/// 2. `/run/user/<uid>`, if it exists and is owned by the current user.
+///    It is usually still present for cron jobs, or after `sudo`.
+/// 3. `<tmp>/alive_lock_file-<uid>`, created with permissions `0700`.
+///    If it already exist, it must be a directory owned by the current user,
+///    that no one else can access, otherwise an error is returned.
+///    Most system services and containers end up here.
+///
+/// The two last steps are only used on unix.
+pub fn runtime_dir() -> Result<PathBuf> {
+    if let Some(dir) = dirs::runtime_dir().filter(|dir| dir.is_dir()) {
+        return Ok(dir);
+    }
+
+    fallback_runtime_dir()
+}
+
+#[cfg(unix)]
+fn fallback_runtime_dir() -> Result<PathBuf> {
+    use std::{
+        fs,
+        os::unix::fs::{DirBuilderExt, MetadataExt},
+    };
+
+    use crate::{error::IoResultExt, Error};
+
+    let uid = crate::sys::euid();
+
+    let run_user = PathBuf::from(format!("/run/user/{uid}"));
+    if fs::symlink_metadata(&run_user)
+        .is_ok_and(|metadata| metadata.is_dir() && metadata.uid() == uid)
+    {
+        return Ok(run_user);
+    }
+
+    let tmp = env::temp_dir().join(format!("alive_lock_file-{uid}"));
+
+    match fs::DirBuilder::new().mode(0o700).create(&tmp) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(Error::io(&tmp, e)),
+    }
+
+    // another user could have created it before us, to read or replace our lock files
+    let metadata = fs::symlink_metadata(&tmp).with_path(&tmp)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(Error::NoRuntimeDir { path: tmp });
+    }
+
+    Ok(tmp)
+}
+
+/// The temporary directory is already private to the user on these platforms.
+#[cfg(not(unix))]
+fn fallback_runtime_dir() -> Result<PathBuf> {
+    Ok(env::temp_dir().join("alive_lock_file"))
+}
@@ -0,0 +1,78 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Error returned by this crate.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// No directory can be used to create lock files: the fallback of [`crate::runtime_dir`]
+    /// exist, but is not a private directory owned by the current user.
+    #[error("no runtime dir: {} is not a private directory owned by the current user", path.display())]
+    NoRuntimeDir { path: PathBuf },
+    /// The lock name can't be used.
+    #[error("invalid lock name {name:?}: {reason}")]
+    InvalidName { name: String, reason: &'static str },
+    /// The lock file has no parent directory.
+    #[error("{} has no parent directory", path.display())]
+    ParentMissing { path: PathBuf },
+    /// The current user is not allowed to access this file.
+    #[error("permission denied on {}: {source}", path.display())]
+    PermissionDenied { path: PathBuf, source: io::Error },
+    /// The lock file exist, but was not created by this crate, or is corrupted.
+    #[error("invalid lock file {}: {reason}", path.display())]
+    InvalidLockFile { path: PathBuf, reason: String },
+    /// The lock file was removed or replaced by another process while the lock was held.
+    #[error("lock {} was lost: the lock file was removed or replaced by another process", path.display())]
+    LockLost { path: PathBuf },
+    /// The signal handlers can't be installed.
+    #[cfg(all(unix, feature = "signal"))]
+    #[error("can't install signal handlers: {0}")]
+    Signal(#[source] io::Error),
+    /// The operation is not supported on this platform.
+    #[error("{0} is not supported on this platform")]
+    Unsupported(&'static str),
+    /// Any other I/O error.
+    #[error("I/O error on {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        if source.kind() == io::ErrorKind::PermissionDenied {
+            Self::PermissionDenied { path, source }
+        } else {
+            Self::Io { path, source }
+        }
+    }
+
+    /// Get the path concerned by this error, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::NoRuntimeDir { path }
+            | Self::ParentMissing { path }
+            | Self::PermissionDenied { path, .. }
+            | Self::InvalidLockFile { path, .. }
+            | Self::LockLost { path }
+            | Self::Io { path, .. } => Some(path),
+            Self::InvalidName { .. } | Self::Unsupported(_) => None,
+            #[cfg(all(unix, feature = "signal"))]
+            Self::Signal(_) => None,
+        }
+    }
+}
+
+/// Attach the path of the file to I/O errors.
+pub(crate) trait IoResultExt<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|e| Error::io(path, e))
+    }
+}
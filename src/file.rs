@@ -0,0 +1,632 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+#[cfg(unix)]
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+
+#[cfg(unix)]
+use crate::wait::{Backoff, WaitStrategy};
+use crate::{
+    error::IoResultExt,
+    sys::{self, FileId},
+    Error, LockInfo, LockMode, LockOptions, LockState, RemoveResult, Result, StalePolicy,
+};
+
+/// Lock file created by this process, recognized before removing it,
+/// so a lock file created by another process after ours was removed is never removed.
+#[derive(Debug, Clone)]
+pub(crate) struct OwnedFile {
+    id: Option<FileId>,
+    /// Content written in the file. `None` if the file is kept open, so its inode can't be reused.
+    record: Option<String>,
+}
+
+impl OwnedFile {
+    /// A lock file kept open for the lifetime of the lock.
+    pub fn open(path: &Path, file: &File) -> Result<Self> {
+        let metadata = file.metadata().with_path(path)?;
+        Ok(Self {
+            id: sys::file_id(&metadata),
+            record: None,
+        })
+    }
+
+    /// Return true if the file at this path is still the one we created.
+    pub fn is_at(&self, path: &Path) -> bool {
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+
+        if sys::file_id(&metadata) != self.id {
+            return false;
+        }
+
+        // the inode of a removed file can be reused by a new file
+        match &self.record {
+            Some(record) => fs::read_to_string(path).is_ok_and(|content| &content == record),
+            None => true,
+        }
+    }
+
+    /// Open the file at this path for writing, if it is still the one we created.
+    /// Unlike the path, the opened file can't be replaced by another process.
+    pub fn open_at(&self, path: &Path) -> io::Result<Option<File>> {
+        let mut file = match File::options().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if sys::file_id(&file.metadata()?) != self.id {
+            return Ok(None);
+        }
+
+        if let Some(record) = &self.record {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            if content != record.as_bytes() {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(file))
+    }
+}
+
+/// Create the lock file.
+/// Return `None` if it is held by another process.
+pub(crate) fn create_log_file(path: &Path, options: &LockOptions) -> Result<Option<OwnedFile>> {
+    create_parents(path)?;
+
+    if let Some(owned) = create_new_with_info(path, options)? {
+        return Ok(Some(owned));
+    }
+
+    if options.stale_policy == StalePolicy::Keep || !remove_stale_lock_file(path)? {
+        return Ok(None);
+    }
+
+    create_new_with_info(path, options)
+}
+
+/// Remove the lock file if it is stale.
+/// Return false if it is held.
+///
+/// On unix, the file is removed while holding an exclusive `flock` on it.
+/// So among the processes that found the same stale file, only one can remove it,
+/// and a lock file created in the meantime is never removed.
+#[cfg(unix)]
+fn remove_stale_lock_file(path: &Path) -> Result<bool> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => return Ok(false),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+
+    if !sys::try_flock(&file, true).with_path(path)? {
+        return Ok(false);
+    }
+
+    // another process could have removed the stale file and created a new one before we locked it
+    match fs::metadata(path) {
+        Ok(metadata) if sys::same_file(&metadata, &file.metadata().with_path(path)?) => {}
+        Ok(_) => return Ok(false),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(Error::io(path, e)),
+    }
+
+    if file_lock_state(path, &mut file, false)?.is_held() {
+        return Ok(false);
+    }
+
+    warn!("reclaiming stale lock file {}", path.display());
+
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(Error::io(path, e)),
+    }
+}
+
+/// Remove the lock file if it is stale.
+/// Return false if it is held.
+///
+/// Without `flock`, two processes that found the same stale file could both remove it.
+#[cfg(not(unix))]
+fn remove_stale_lock_file(path: &Path) -> Result<bool> {
+    if read_lock_state(path)?.is_held() {
+        return Ok(false);
+    }
+
+    warn!("reclaiming stale lock file {}", path.display());
+
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(Error::io(path, e)),
+    }
+}
+
+/// Remove the lock file if it was created by this crate,
+/// and if its holder is the current process or is not running anymore, unless `force` is set.
+pub(crate) fn remove_lock_file(path: &Path, force: bool) -> Result<RemoveResult> {
+    use std::io::Read;
+
+    loop {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(RemoveResult::NotFound),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+
+        // like for stale files, holding a `flock` while removing the file ensure that a lock
+        // acquired in the meantime is never removed
+        #[cfg(unix)]
+        let kernel_locked = !sys::try_flock(&file, true).with_path(path)?;
+        #[cfg(not(unix))]
+        let kernel_locked = false;
+
+        #[cfg(unix)]
+        match fs::metadata(path) {
+            Ok(metadata) if sys::same_file(&metadata, &file.metadata().with_path(path)?) => {}
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(RemoveResult::NotFound),
+            Err(e) => return Err(Error::io(path, e)),
+        }
+
+        let mut content = String::new();
+        match file.read_to_string(&mut content) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                return Err(Error::InvalidLockFile {
+                    path: path.to_path_buf(),
+                    reason: "not a lock file created by this crate".to_owned(),
+                })
+            }
+            Err(e) => return Err(Error::io(path, e)),
+        }
+
+        // an empty file is being written by a `flock` holder, or was left by an older version of this crate
+        let (info, held) = if content.is_empty() {
+            (None, kernel_locked)
+        } else {
+            let info = LockInfo::parse(&content).map_err(|reason| Error::InvalidLockFile {
+                path: path.to_path_buf(),
+                reason,
+            })?;
+            // the current process release the locks acquired with `try_lock` by removing them,
+            // but its other locks are held until their `Lock` is dropped
+            let persistent = info.is_current_process() && is_persistent_lock(path);
+            let held = !persistent && (kernel_locked || is_holder_running(path, &info, &file)?);
+            (Some(info), held)
+        };
+
+        if held {
+            if !force {
+                return Ok(RemoveResult::Held(info));
+            }
+            warn!("breaking lock {} held by a running process", path.display());
+        }
+
+        return match fs::remove_file(path) {
+            Ok(()) => {
+                remove_persistent_lock(path);
+                Ok(RemoveResult::Removed(info))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(RemoveResult::NotFound),
+            Err(e) => Err(Error::io(path, e)),
+        };
+    }
+}
+
+/// Lock files acquired by this process with `try_lock`, which stay held until they are removed.
+static PERSISTENT_LOCKS: Mutex<Vec<(PathBuf, OwnedFile)>> = Mutex::new(Vec::new());
+
+/// Remember a lock file acquired with `try_lock`, so the current process can release it with `remove_lock`.
+pub(crate) fn add_persistent_lock(path: &Path, owned: OwnedFile) {
+    persistent_locks().push((path.to_path_buf(), owned));
+}
+
+/// Return true if the file at this path is a lock acquired by this process with `try_lock`.
+fn is_persistent_lock(path: &Path) -> bool {
+    persistent_locks()
+        .iter()
+        .any(|(persistent, owned)| persistent == path && owned.is_at(path))
+}
+
+fn remove_persistent_lock(path: &Path) {
+    persistent_locks().retain(|(persistent, _)| persistent != path);
+}
+
+fn persistent_locks() -> MutexGuard<'static, Vec<(PathBuf, OwnedFile)>> {
+    PERSISTENT_LOCKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Atomically create the file containing the [`LockInfo`] of this process.
+/// Return `None` if the file already exist.
+fn create_new_with_info(path: &Path, options: &LockOptions) -> Result<Option<OwnedFile>> {
+    static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::InvalidName {
+            name: path.to_string_lossy().into_owned(),
+            reason: "no file name",
+        })?
+        .to_string_lossy();
+
+    // the file is written under a temporary name, and then hard linked to its real name,
+    // so other processes never see a partially written lock file
+    let tmp_path = path.with_file_name(format!(
+        ".{file_name}.{}.{}.tmp",
+        process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let record = options.lock_info().to_record();
+
+    let res = open_options(options)
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(record.as_bytes())?;
+            file.metadata()
+        })
+        .and_then(|metadata| {
+            fs::hard_link(&tmp_path, path)?;
+            Ok(metadata)
+        });
+
+    if let Err(e) = fs::remove_file(&tmp_path) {
+        error!("can't remove temporary file {}: {e}", tmp_path.display());
+    }
+
+    match res {
+        Ok(metadata) => Ok(Some(OwnedFile {
+            id: sys::file_id(&metadata),
+            record: Some(record),
+        })),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+        Err(e) => Err(Error::io(path, e)),
+    }
+}
+
+/// Open the lock file and take a `flock` on it.
+/// Return `None` if it is held by another process.
+///
+/// The [`LockInfo`] of this process is written in the file,
+/// except in shared mode if other processes already hold it.
+#[cfg(unix)]
+pub(crate) fn create_flock_file(path: &Path, options: &LockOptions) -> Result<Option<File>> {
+    use std::io::{Read, Seek};
+
+    create_parents(path)?;
+
+    let mode = options.mode;
+    let mut retry = SharedRetry::new();
+
+    loop {
+        let mut file = open_options(options)
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_path(path)?;
+
+        // the first holder lock the file exclusively to write its info, even in shared mode
+        let first = if sys::try_flock(&file, true).with_path(path)? {
+            true
+        } else if mode == LockMode::Shared && sys::try_flock(&file, false).with_path(path)? {
+            false
+        } else if mode == LockMode::Shared && is_shared_record(&mut file) && retry.wait() {
+            continue;
+        } else {
+            return Ok(None);
+        };
+
+        // the previous holder could have removed the file between our open and our lock.
+        // In this case, we locked a file that other processes can't see anymore, so we retry.
+        match fs::metadata(path) {
+            Ok(metadata) if sys::same_file(&metadata, &file.metadata().with_path(path)?) => {}
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::io(path, e)),
+        }
+
+        if !first {
+            return Ok(Some(file));
+        }
+
+        // the file can also be held by a process using the file backend
+        let mut content = String::new();
+        file.read_to_string(&mut content).with_path(path)?;
+
+        if !content.is_empty() {
+            match LockInfo::parse(&content) {
+                Ok(info) if is_holder_running(path, &info, &file)? => return Ok(None),
+                Ok(_) if options.stale_policy == StalePolicy::Keep => return Ok(None),
+                Ok(_) => warn!("reclaiming stale lock file {}", path.display()),
+                Err(_) => return Ok(None),
+            }
+        }
+
+        file.set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| file.write_all(options.lock_info().to_record().as_bytes()))
+            .with_path(path)?;
+
+        // another process could lock the file exclusively while the lock is converted
+        if mode == LockMode::Shared && !sys::try_flock(&file, false).with_path(path)? {
+            continue;
+        }
+
+        return Ok(Some(file));
+    }
+}
+
+/// How long a shared holder can hold the file exclusively, while it write its info or release the lock.
+#[cfg(unix)]
+const SHARED_RETRY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Retry to acquire a shared lock, held exclusively by another shared holder for a moment.
+#[cfg(unix)]
+struct SharedRetry {
+    backoff: Backoff,
+    deadline: Instant,
+}
+
+#[cfg(unix)]
+impl SharedRetry {
+    fn new() -> Self {
+        Self {
+            backoff: Backoff::new(WaitStrategy::Backoff {
+                initial: Duration::from_micros(100),
+                max: Duration::from_millis(10),
+            }),
+            deadline: Instant::now() + SHARED_RETRY_TIMEOUT,
+        }
+    }
+
+    /// Sleep before the next attempt. Return false if the holder kept the file for too long.
+    fn wait(&mut self) -> bool {
+        if Instant::now() >= self.deadline {
+            return false;
+        }
+        thread::sleep(self.backoff.next_delay(Some(self.deadline)));
+        true
+    }
+}
+
+/// Return true if the file is being written, or was written by a shared holder.
+/// An exclusive holder write `mode exclusive` in it.
+#[cfg(unix)]
+fn is_shared_record(file: &mut File) -> bool {
+    use std::io::Read;
+
+    let mut content = String::new();
+    if file.read_to_string(&mut content).is_err() {
+        return false;
+    }
+    LockInfo::parse(&content).map_or(true, |info| info.mode == LockMode::Shared)
+}
+
+/// Read the state of the lock file.
+///
+/// A lock is held if a process hold a `flock` on it,
+/// or if the process that created it in exclusive mode is still running.
+pub(crate) fn read_lock_state(path: &Path) -> Result<LockState> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(LockState::Free),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => return Ok(LockState::Unknown),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+
+    #[cfg(unix)]
+    let kernel_locked = !sys::try_flock(&file, true).with_path(path)?;
+    #[cfg(not(unix))]
+    let kernel_locked = false;
+
+    file_lock_state(path, &mut file, kernel_locked)
+}
+
+/// Read the state of an open lock file, knowing if it is locked by the kernel.
+fn file_lock_state(path: &Path, file: &mut File, kernel_locked: bool) -> Result<LockState> {
+    use std::io::Read;
+
+    let mut content = String::new();
+    match file.read_to_string(&mut content) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::InvalidData => return Ok(LockState::Unknown),
+        Err(e) => return Err(Error::io(path, e)),
+    }
+
+    if content.is_empty() {
+        // on unix, an empty file not locked by the kernel was left by a `flock` holder that died
+        // before writing its info. Otherwise it was created by an older version of this crate.
+        return Ok(if kernel_locked || !cfg!(unix) {
+            LockState::Unknown
+        } else {
+            LockState::Free
+        });
+    }
+
+    let Ok(info) = LockInfo::parse(&content) else {
+        return Ok(LockState::Unknown);
+    };
+
+    if kernel_locked || is_holder_running(path, &info, file)? {
+        Ok(LockState::Held(info))
+    } else {
+        Ok(LockState::Stale(info))
+    }
+}
+
+/// Return false if the holder of this lock file is known to be gone.
+fn is_holder_running(path: &Path, info: &LockInfo, file: &File) -> Result<bool> {
+    if let Some(ttl) = info.lease_ttl {
+        // the holder could run on another host or in another pid namespace, so only the lease is checked
+        let modified = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .with_path(path)?;
+        return Ok(modified.elapsed().map_or(true, |age| age < ttl));
+    }
+
+    // shared locks are always held with a `flock`
+    Ok(info.mode == LockMode::Exclusive && info.is_holder_running())
+}
+
+/// Create the directories containing the lock file.
+fn create_parents(path: &Path) -> Result<()> {
+    let parents = path.parent().ok_or_else(|| Error::ParentMissing {
+        path: path.to_path_buf(),
+    })?;
+
+    fs::create_dir_all(parents).with_path(parents)
+}
+
+/// [`OpenOptions`] creating files with the permissions set in the options.
+fn open_options(options: &LockOptions) -> OpenOptions {
+    let mut open_options = OpenOptions::new();
+
+    #[cfg(unix)]
+    if let Some(file_mode) = options.file_mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(file_mode);
+    }
+    #[cfg(not(unix))]
+    let _ = options;
+
+    open_options
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf, process::Command};
+
+    use super::*;
+    use crate::{Backend, LockResult, LockResultWithDrop};
+
+    /// Options creating the locks in a directory of this test.
+    fn options(test: &str) -> (LockOptions, PathBuf) {
+        let dir = env::temp_dir().join(format!("alive_lock_file_{test}_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut options = LockOptions::new();
+        options.dir(&dir);
+        let path = dir.join("test.lock");
+        (options, path)
+    }
+
+    #[test]
+    fn remove_lock_of_current_process() {
+        let (options, path) = options("remove_current");
+
+        assert!(matches!(
+            options.try_lock_persistent(&path).unwrap(),
+            LockResult::Success
+        ));
+        assert!(read_lock_state(&path).unwrap().is_held());
+
+        match remove_lock_file(&path, false).unwrap() {
+            RemoveResult::Removed(Some(info)) => assert_eq!(info.pid, process::id()),
+            res => panic!("{res:?}"),
+        }
+        assert_eq!(read_lock_state(&path).unwrap(), LockState::Free);
+        assert_eq!(
+            remove_lock_file(&path, false).unwrap(),
+            RemoveResult::NotFound
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn held_lock_of_current_process_is_kept() {
+        let (mut options, path) = options("remove_held");
+
+        let mut backends = vec![Backend::File];
+        #[cfg(unix)]
+        backends.push(Backend::Flock);
+
+        for backend in backends {
+            options.backend(backend);
+            let LockResultWithDrop::Locked(lock) = options.try_lock_path(&path).unwrap() else {
+                panic!("the lock is free");
+            };
+
+            assert!(matches!(
+                remove_lock_file(&path, false).unwrap(),
+                RemoveResult::Held(Some(_))
+            ));
+            assert!(!lock.is_lost(), "{backend:?}");
+
+            lock.release().unwrap();
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remove_lock_of_another_process() {
+        let (options, path) = options("remove_other");
+        create_parents(&path).unwrap();
+
+        let mut child = Command::new("sleep").arg("60").spawn().unwrap();
+        let mut info = options.lock_info();
+        info.pid = child.id();
+        info.start_time = sys::start_time(child.id());
+        fs::write(&path, info.to_record()).unwrap();
+
+        assert_eq!(
+            remove_lock_file(&path, false).unwrap(),
+            RemoveResult::Held(Some(info.clone()))
+        );
+        assert!(path.exists());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(
+            remove_lock_file(&path, false).unwrap(),
+            RemoveResult::Removed(Some(info))
+        );
+        assert!(!path.exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn files_not_created_by_this_crate_are_kept() {
+        let (_, path) = options("remove_foreign");
+        create_parents(&path).unwrap();
+        fs::write(&path, "some data\n").unwrap();
+
+        assert!(matches!(
+            remove_lock_file(&path, true),
+            Err(Error::InvalidLockFile { .. })
+        ));
+        assert!(path.exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::RwLock;
+
+use log::error;
+
+use crate::Error;
+
+type Hook = Box<dyn Fn(&Error) + Send + Sync>;
+
+static RELEASE_ERROR_HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Register a hook called when a [`crate::Lock`] can't be released when it is dropped,
+/// replacing the previous one.
+///
+/// By default, these errors are logged with [`log::error`].
+/// Use [`crate::Lock::release`] to handle the error of a specific lock.
+///
+/// ```no_run
+/// alive_lock_file::set_release_error_hook(|e| {
+///     eprintln!("can't release lock: {e}");
+/// });
+/// ```
+pub fn set_release_error_hook<F>(hook: F)
+where
+    F: Fn(&Error) + Send + Sync + 'static,
+{
+    let mut current = RELEASE_ERROR_HOOK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *current = Some(Box::new(hook));
+}
+
+/// Unregister the hook, so release errors are logged again.
+pub fn reset_release_error_hook() {
+    let mut current = RELEASE_ERROR_HOOK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *current = None;
+}
+
+/// Report an error that happened while dropping a lock.
+pub(crate) fn release_error(e: &Error) {
+    let hook = RELEASE_ERROR_HOOK
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    match hook.as_ref() {
+        Some(hook) => hook(e),
+        None => error!("can't release lock: {e}"),
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    process,
+    time::{Duration, SystemTime},
+};
+
+use crate::{sys, LockMode};
+
+/// First line of every lock file created by this crate.
+pub(crate) const HEADER: &str = "alive_lock_file";
+/// Version of the format of the lock files.
+const VERSION: u32 = 1;
+
+/// Information about the process that created a lock, written in the lock file.
+///
+/// In shared mode, this is the process that created the lock file,
+/// which may not be one of the current holders anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockInfo {
+    pub pid: u32,
+    /// Start time of the process, in clock ticks since boot (linux only).
+    pub start_time: Option<u64>,
+    pub mode: LockMode,
+    pub hostname: Option<String>,
+    pub uid: Option<u32>,
+    /// When the lock was acquired.
+    pub acquired_at: SystemTime,
+    pub exe: Option<PathBuf>,
+    /// Command line arguments, including the program name.
+    pub args: Vec<String>,
+    /// Identifier of the boot during which the lock was acquired (linux only).
+    pub boot_id: Option<String>,
+    /// Time after which the lock expire if it was not refreshed, for locks held with a [`crate::Lease`].
+    pub lease_ttl: Option<Duration>,
+}
+
+impl LockInfo {
+    /// Information about the current process.
+    pub(crate) fn current(mode: LockMode) -> Self {
+        Self {
+            pid: process::id(),
+            start_time: sys::start_time(process::id()),
+            mode,
+            hostname: sys::hostname(),
+            uid: sys::uid(),
+            acquired_at: SystemTime::now(),
+            exe: std::env::current_exe().ok(),
+            args: std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            boot_id: sys::boot_id(),
+            lease_ttl: None,
+        }
+    }
+
+    /// Return false if the process that created the lock is known to be not running anymore.
+    pub(crate) fn is_holder_running(&self) -> bool {
+        if self
+            .hostname
+            .as_ref()
+            .is_some_and(|hostname| Some(hostname) != sys::hostname().as_ref())
+        {
+            // we can't check processes of another host
+            return true;
+        }
+
+        if self.boot_id.is_some() && self.boot_id != sys::boot_id() {
+            return false;
+        }
+
+        if !sys::is_alive(self.pid) {
+            return false;
+        }
+
+        // the pid could have been reused by another process after the holder died
+        match self.start_time {
+            Some(start_time) => sys::start_time(self.pid) == Some(start_time),
+            None => true,
+        }
+    }
+
+    /// Return true if the lock was created by the current process.
+    pub(crate) fn is_current_process(&self) -> bool {
+        self.pid == process::id()
+            && self.start_time == sys::start_time(self.pid)
+            && self.hostname == sys::hostname()
+            && self.boot_id == sys::boot_id()
+    }
+
+    /// Serialize to the content of a lock file.
+    pub(crate) fn to_record(&self) -> String {
+        let mut record = format!("{HEADER} {VERSION}\n");
+
+        let mut field = |key: &str, value: &str| {
+            writeln!(record, "{key} {}", escape(value)).unwrap();
+        };
+
+        field("pid", &self.pid.to_string());
+        if let Some(start_time) = self.start_time {
+            field("start_time", &start_time.to_string());
+        }
+        field(
+            "mode",
+            match self.mode {
+                LockMode::Shared => "shared",
+                LockMode::Exclusive => "exclusive",
+            },
+        );
+        if let Some(hostname) = &self.hostname {
+            field("hostname", hostname);
+        }
+        if let Some(uid) = self.uid {
+            field("uid", &uid.to_string());
+        }
+        let acquired_at = self
+            .acquired_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        field(
+            "acquired_at",
+            &format!(
+                "{}.{:09}",
+                acquired_at.as_secs(),
+                acquired_at.subsec_nanos()
+            ),
+        );
+        if let Some(exe) = &self.exe {
+            field("exe", &exe.to_string_lossy());
+        }
+        for arg in &self.args {
+            field("arg", arg);
+        }
+        if let Some(boot_id) = &self.boot_id {
+            field("boot_id", boot_id);
+        }
+        if let Some(lease_ttl) = self.lease_ttl {
+            field("lease_ttl", &format_duration(lease_ttl));
+        }
+
+        record
+    }
+
+    /// Parse the content of a lock file.
+    /// Return the reason why it is invalid on error.
+    pub(crate) fn parse(record: &str) -> Result<Self, String> {
+        let mut lines = record.lines();
+
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or("not a lock file created by this crate")?;
+
+        if version != VERSION {
+            return Err(format!("unsupported lock file version {version}"));
+        }
+
+        let mut pid = None;
+        let mut info = Self {
+            pid: 0,
+            start_time: None,
+            mode: LockMode::Exclusive,
+            hostname: None,
+            uid: None,
+            acquired_at: SystemTime::UNIX_EPOCH,
+            exe: None,
+            args: Vec::new(),
+            boot_id: None,
+            lease_ttl: None,
+        };
+
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = unescape(value);
+
+            match key {
+                "pid" => pid = value.parse().ok(),
+                "start_time" => info.start_time = value.parse().ok(),
+                "mode" => {
+                    info.mode = match value.as_str() {
+                        "shared" => LockMode::Shared,
+                        "exclusive" => LockMode::Exclusive,
+                        _ => return Err(format!("invalid lock mode {value}")),
+                    }
+                }
+                "hostname" => info.hostname = Some(value),
+                "uid" => info.uid = value.parse().ok(),
+                "acquired_at" => {
+                    info.acquired_at = SystemTime::UNIX_EPOCH
+                        .checked_add(parse_duration(&value)?)
+                        .ok_or_else(|| format!("invalid time {value}"))?
+                }
+                "exe" => info.exe = Some(PathBuf::from(value)),
+                "arg" => info.args.push(value),
+                "boot_id" => info.boot_id = Some(value),
+                "lease_ttl" => info.lease_ttl = Some(parse_duration(&value)?),
+                // written by a newer version of this crate
+                _ => {}
+            }
+        }
+
+        info.pid = pid.ok_or("no pid in lock file")?;
+
+        Ok(info)
+    }
+}
+
+/// Format a duration as seconds, with nanoseconds precision.
+fn format_duration(duration: Duration) -> String {
+    format!("{}.{:09}", duration.as_secs(), duration.subsec_nanos())
+}
+
+/// Parse a duration written by [`format_duration`]. The fraction can have less than 9 digits.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {value}");
+
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let secs = secs.parse().map_err(|_| invalid())?;
+    // "1.5" is 1s and 500ms, not 5ns
+    let nanos = format!("{fraction:0<9}").parse().map_err(|_| invalid())?;
+
+    Ok(Duration::new(secs, nanos))
+}
+
+/// Escape the characters that would break the line based format.
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> LockInfo {
+        LockInfo {
+            pid: 1234,
+            start_time: Some(5678),
+            mode: LockMode::Shared,
+            hostname: Some("host".to_owned()),
+            uid: Some(1000),
+            acquired_at: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+            exe: Some(PathBuf::from("/usr/bin/my app")),
+            args: vec![
+                "my app".to_owned(),
+                "line\nbreak".to_owned(),
+                "back\\slash".to_owned(),
+                "literal \\n".to_owned(),
+                "carriage\rreturn".to_owned(),
+                "trailing\\".to_owned(),
+                String::new(),
+            ],
+            boot_id: Some("boot".to_owned()),
+            lease_ttl: Some(Duration::from_millis(1500)),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let info = info();
+        let record = info.to_record();
+
+        assert!(record.starts_with("alive_lock_file 1\n"));
+        // each field is on its own line
+        assert_eq!(record.lines().count(), 10 + info.args.len());
+        assert_eq!(LockInfo::parse(&record), Ok(info));
+    }
+
+    #[test]
+    fn round_trip_without_optional_fields() {
+        let info = LockInfo {
+            start_time: None,
+            mode: LockMode::Exclusive,
+            hostname: None,
+            uid: None,
+            exe: None,
+            args: Vec::new(),
+            boot_id: None,
+            lease_ttl: None,
+            ..info()
+        };
+
+        assert_eq!(LockInfo::parse(&info.to_record()), Ok(info));
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let record = "alive_lock_file 1\npid 42\nfuture_key some value\nacquired_at 10.5\n";
+        let info = LockInfo::parse(record).unwrap();
+
+        assert_eq!(info.pid, 42);
+        assert_eq!(info.mode, LockMode::Exclusive);
+        assert_eq!(
+            info.acquired_at,
+            SystemTime::UNIX_EPOCH + Duration::from_millis(10_500)
+        );
+    }
+
+    #[test]
+    fn invalid_records() {
+        for record in [
+            "",
+            "pid 42\n",
+            "another_crate 1\npid 42\n",
+            "alive_lock_file 2\npid 42\n",
+            "alive_lock_file x\npid 42\n",
+            "alive_lock_file 1\n",
+            "alive_lock_file 1\npid x\n",
+            "alive_lock_file 1\npid 42\nmode other\n",
+            "alive_lock_file 1\npid 42\nacquired_at 18446744073709551615.0\n",
+            "alive_lock_file 1\npid 42\nlease_ttl 1.x\n",
+        ] {
+            assert!(LockInfo::parse(record).is_err(), "{record:?}");
+        }
+    }
+
+    #[test]
+    fn wrong_version() {
+        assert_eq!(
+            LockInfo::parse("alive_lock_file 2\npid 42\n"),
+            Err("unsupported lock file version 2".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_pid() {
+        assert_eq!(
+            LockInfo::parse("alive_lock_file 1\nhostname host\n"),
+            Err("no pid in lock file".to_owned())
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("1"), Ok(Duration::from_secs(1)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("1.000000001"), Ok(Duration::new(1, 1)));
+        assert_eq!(
+            parse_duration("0.123456789"),
+            Ok(Duration::new(0, 123_456_789))
+        );
+
+        for value in ["", "1.", ".5", "1.1234567890", "1.-5", "1.+5", "-1.0", "x"] {
+            assert!(parse_duration(value).is_err(), "{value:?}");
+        }
+
+        let duration = Duration::new(u64::MAX, 999_999_999);
+        assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+    }
+
+    #[test]
+    fn escaping() {
+        for value in ["", "plain", "a\nb", "a\\nb", "a\\", "\r\n\\", "é\n"] {
+            let escaped = escape(value);
+            assert!(!escaped.contains(['\n', '\r']));
+            assert_eq!(unescape(&escaped), value);
+        }
+
+        assert_eq!(escape("a\\b\nc"), "a\\\\b\\nc");
+        // a lone trailing backslash is kept
+        assert_eq!(unescape("a\\"), "a\\");
+    }
+}
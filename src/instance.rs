@@ -0,0 +1,360 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, ErrorKind, Read, Write},
+    mem,
+    net::Shutdown,
+    os::{
+        fd::AsRawFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+
+use crate::{
+    error::IoResultExt,
+    info::{escape, unescape},
+    wait::Backoff,
+    Error, Lock, LockOptions, LockResultWithDrop, Result,
+};
+
+/// How long to wait for the primary instance to listen on its socket.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for a secondary instance to send its message.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Result of [`crate::single_instance`].
+#[derive(Debug)]
+#[must_use]
+pub enum SingleInstance {
+    /// This process is the primary instance.
+    Primary(Box<Primary>),
+    /// Another instance is running, and the [`InstanceMessage`] of this process was forwarded to it.
+    /// This process should exit.
+    Forwarded,
+}
+
+/// Message sent by a secondary instance to the primary instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InstanceMessage {
+    pub pid: u32,
+    /// Command line arguments, including the program name.
+    pub args: Vec<String>,
+    /// Working directory of the secondary instance.
+    pub cwd: Option<PathBuf>,
+    pub message: Option<String>,
+}
+
+/// The primary instance of an application.
+///
+/// It hold the lock, and listen on a unix socket next to the lock file for messages of the other instances.
+/// The socket is removed, and the lock released, when it is dropped.
+#[derive(Debug)]
+pub struct Primary {
+    socket: PathBuf,
+    messages: Receiver<InstanceMessage>,
+    /// The listener thread stop when this end of the pair is closed.
+    wake: Option<UnixStream>,
+    thread: Option<JoinHandle<()>>,
+    /// Process that created the listener thread.
+    pid: u32,
+    lock: Lock,
+}
+
+impl InstanceMessage {
+    /// Message of the current process.
+    fn current(message: Option<&str>) -> Self {
+        Self {
+            pid: process::id(),
+            args: std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            cwd: std::env::current_dir().ok(),
+            message: message.map(str::to_owned),
+        }
+    }
+
+    /// Serialize with the same line based format as the lock files.
+    fn to_record(&self) -> String {
+        let mut record = String::new();
+
+        let mut field = |key: &str, value: &str| {
+            writeln!(record, "{key} {}", escape(value)).unwrap();
+        };
+
+        field("pid", &self.pid.to_string());
+        for arg in &self.args {
+            field("arg", arg);
+        }
+        if let Some(cwd) = &self.cwd {
+            field("cwd", &cwd.to_string_lossy());
+        }
+        if let Some(message) = &self.message {
+            field("message", message);
+        }
+
+        record
+    }
+
+    fn parse(record: &str) -> Self {
+        let mut message = Self {
+            pid: 0,
+            args: Vec::new(),
+            cwd: None,
+            message: None,
+        };
+
+        for line in record.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = unescape(value);
+
+            match key {
+                "pid" => message.pid = value.parse().unwrap_or_default(),
+                "arg" => message.args.push(value),
+                "cwd" => message.cwd = Some(PathBuf::from(value)),
+                "message" => message.message = Some(value),
+                _ => {}
+            }
+        }
+
+        message
+    }
+}
+
+impl Primary {
+    /// Messages received from the other instances.
+    pub fn messages(&self) -> &Receiver<InstanceMessage> {
+        &self.messages
+    }
+
+    /// Get the lock held by this instance.
+    pub fn lock(&self) -> &Lock {
+        &self.lock
+    }
+
+    /// Get the path of the socket.
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    fn listen(lock: Lock, socket: PathBuf) -> Result<Self> {
+        // we hold the lock, so a socket at this path was left by a previous primary instance
+        match fs::remove_file(&socket) {
+            Ok(()) => warn!("removing stale socket {}", socket.display()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io(&socket, e)),
+        }
+
+        let listener = UnixListener::bind(&socket).with_path(&socket)?;
+        // a client can disconnect between the wake up and the accept, which must not block
+        listener.set_nonblocking(true).with_path(&socket)?;
+        let (wake, woken) = UnixStream::pair().with_path(&socket)?;
+        let (sender, messages) = mpsc::channel();
+
+        let thread = {
+            let socket = socket.clone();
+            thread::spawn(move || accept(listener, &woken, &socket, &sender))
+        };
+
+        Ok(Self {
+            socket,
+            messages,
+            wake: Some(wake),
+            thread: Some(thread),
+            pid: process::id(),
+            lock,
+        })
+    }
+}
+
+impl Drop for Primary {
+    fn drop(&mut self) {
+        // the listener thread only exist in the parent of a forked process
+        if self.pid != process::id() {
+            mem::forget(self.thread.take());
+            return;
+        }
+
+        // wake up the listener thread, even if the socket was removed or replaced
+        drop(self.wake.take());
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("single instance listener thread panicked");
+            }
+        }
+
+        // the socket must be removed before the lock is released
+        if let Err(e) = fs::remove_file(&self.socket) {
+            error!("can't remove socket {}: {e}", self.socket.display());
+        }
+    }
+}
+
+/// Receive the messages of the other instances, until the other end of `woken` is closed.
+fn accept(
+    listener: UnixListener,
+    woken: &UnixStream,
+    socket: &Path,
+    sender: &Sender<InstanceMessage>,
+) {
+    let mut fds = [listener.as_raw_fd(), woken.as_raw_fd()].map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+
+    loop {
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ready < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            error!("can't wait for messages on {}: {e}", socket.display());
+            return;
+        }
+
+        // the primary instance was dropped
+        if fds[1].revents != 0 {
+            return;
+        }
+
+        let stream = match listener.accept() {
+            Ok((stream, _)) => Ok(stream),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+            Err(e) => Err(e),
+        };
+
+        let mut record = String::new();
+        let res = stream.and_then(|mut stream| {
+            // accepted streams inherit the non blocking mode of the listener on some systems
+            stream.set_nonblocking(false)?;
+            // don't let a stuck client block the other instances
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+            stream.read_to_string(&mut record)?;
+            Ok(stream)
+        });
+
+        match res {
+            Ok(stream) => {
+                // the receiver can be dropped while the primary instance is still running
+                let _ = sender.send(InstanceMessage::parse(&record));
+                drop(stream);
+            }
+            Err(e) => error!("can't receive message on {}: {e}", socket.display()),
+        }
+    }
+}
+
+/// Path of the socket of the primary instance, next to its lock file.
+fn socket_path(lock_path: &Path) -> PathBuf {
+    let mut socket = lock_path.as_os_str().to_owned();
+    socket.push(".sock");
+    PathBuf::from(socket)
+}
+
+/// Become the primary instance, or forward the [`InstanceMessage`] of this process to it.
+pub(crate) fn single_instance(
+    options: &LockOptions,
+    path: &Path,
+    message: Option<&str>,
+) -> Result<SingleInstance> {
+    let socket = socket_path(path);
+    let record = InstanceMessage::current(message).to_record();
+
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let mut backoff = Backoff::new(options.wait_strategy);
+
+    loop {
+        if let LockResultWithDrop::Locked(lock) = options.try_lock_path(path)? {
+            return Primary::listen(lock, socket)
+                .map(|primary| SingleInstance::Primary(Box::new(primary)));
+        }
+
+        // the primary instance could still be starting, or be exiting
+        match UnixStream::connect(&socket) {
+            Ok(stream) => {
+                send(stream, &record).with_path(&socket)?;
+                return Ok(SingleInstance::Forwarded);
+            }
+            Err(e) if Instant::now() >= deadline => return Err(Error::io(&socket, e)),
+            Err(_) => thread::sleep(backoff.next_delay(Some(deadline))),
+        }
+    }
+}
+
+/// Send the message, and wait for the primary instance to receive it.
+fn send(mut stream: UnixStream, record: &str) -> io::Result<()> {
+    stream.write_all(record.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    // the primary instance close the stream once it read the message
+    stream.read_to_end(&mut Vec::new())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn options(test: &str) -> (LockOptions, PathBuf) {
+        let dir = env::temp_dir().join(format!("alive_lock_file_{test}_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut options = LockOptions::new();
+        options.dir(&dir);
+        (options, dir)
+    }
+
+    #[test]
+    fn forward_message() {
+        let (options, dir) = options("instance_forward");
+
+        let SingleInstance::Primary(primary) = options.single_instance("app", None).unwrap() else {
+            panic!("there is no other instance");
+        };
+        assert!(matches!(
+            options.single_instance("app", Some("hello")).unwrap(),
+            SingleInstance::Forwarded
+        ));
+
+        let message = primary.messages().recv_timeout(READ_TIMEOUT).unwrap();
+        assert_eq!(message.pid, process::id());
+        assert_eq!(message.message.as_deref(), Some("hello"));
+
+        drop(primary);
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drop_without_socket() {
+        let (options, dir) = options("instance_drop");
+
+        let SingleInstance::Primary(primary) = options.single_instance("app", None).unwrap() else {
+            panic!("there is no other instance");
+        };
+        fs::remove_file(primary.socket()).unwrap();
+
+        // the listener thread must stop, even if nobody can connect to it anymore
+        let (dropped, done) = mpsc::channel();
+        thread::spawn(move || {
+            drop(primary);
+            dropped.send(()).unwrap();
+        });
+        done.recv_timeout(CONNECT_TIMEOUT).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
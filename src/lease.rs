@@ -0,0 +1,157 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use log::error;
+
+use crate::{error::IoResultExt, file::OwnedFile, Error, Result};
+
+/// Lease of a lock, for holders whose pid can't be checked by other processes,
+/// like processes running on another host or in another pid namespace.
+///
+/// The holder refresh the modification time of the lock file, and other processes
+/// consider the lock expired when it was not refreshed for longer than the ttl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    ttl: Duration,
+    heartbeat: bool,
+}
+
+impl Lease {
+    /// Shortest ttl of a lease. Shorter ttls are rounded up to it.
+    ///
+    /// The modification time of a file can have a coarse resolution,
+    /// and the heartbeat would refresh the lock file in a busy loop.
+    pub const MIN_TTL: Duration = Duration::from_secs(1);
+
+    /// A lease refreshed by a background thread, every third of `ttl`, until the [`crate::Lock`] is dropped.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: ttl.max(Self::MIN_TTL),
+            heartbeat: true,
+        }
+    }
+
+    /// A lease that must be refreshed by calling [`crate::Lock::refresh`] more often than `ttl`.
+    pub fn manual(ttl: Duration) -> Self {
+        Self {
+            ttl: ttl.max(Self::MIN_TTL),
+            heartbeat: false,
+        }
+    }
+
+    /// Get the time after which the lock expire if it was not refreshed.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub(crate) fn spawn_heartbeat(&self, path: &Path, owned: &OwnedFile) -> Option<Heartbeat> {
+        if self.heartbeat {
+            Some(Heartbeat::spawn(
+                path.to_path_buf(),
+                owned.clone(),
+                self.ttl / 3,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Shortest delay between two refreshes of a lease, a third of [`Lease::MIN_TTL`].
+const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(333);
+
+/// Thread refreshing a lease. It stop when this value is dropped, or when the lock is lost.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    fn spawn(path: PathBuf, owned: OwnedFile, interval: Duration) -> Self {
+        let interval = interval.max(MIN_HEARTBEAT_INTERVAL);
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        // the sender is dropped to stop the thread
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match refresh(&path, &owned) {
+                    Ok(()) => {}
+                    Err(e @ Error::LockLost { .. }) => {
+                        error!("stop refreshing lease: {e}");
+                        break;
+                    }
+                    Err(e) => error!("can't refresh lease of {}: {e}", path.display()),
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("lease heartbeat thread panicked");
+            }
+        }
+    }
+}
+
+/// Set the modification time of the lock file to now.
+/// Return [`Error::LockLost`] if the file at this path is not `owned` anymore.
+pub(crate) fn refresh(path: &Path, owned: &OwnedFile) -> Result<()> {
+    match owned.open_at(path).with_path(path)? {
+        Some(file) => file.set_modified(SystemTime::now()).with_path(path),
+        None => Err(Error::LockLost {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{LockOptions, LockResultWithDrop};
+
+    #[test]
+    fn refresh_lost_lock() {
+        let dir = env::temp_dir().join(format!("alive_lock_file_lease_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("test.lock");
+
+        let mut options = LockOptions::new();
+        options.dir(&dir).lease(Lease::manual(Lease::MIN_TTL));
+
+        let LockResultWithDrop::Locked(lock) = options.try_lock_path(&path).unwrap() else {
+            panic!("the lock is free");
+        };
+        lock.refresh().unwrap();
+
+        // another process broke the lock, and acquired it again
+        fs::remove_file(&path).unwrap();
+        let LockResultWithDrop::Locked(other) = options.try_lock_path(&path).unwrap() else {
+            panic!("the lock was broken");
+        };
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        assert!(matches!(lock.refresh(), Err(Error::LockLost { .. })));
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        drop(other);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,54 @@
 use std::{
     fs::{self, File},
     io::ErrorKind,
+    mem,
+    ops::Deref,
     path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Result};
-use log::error;
+#[cfg(unix)]
+use std::os::fd::{AsFd, BorrowedFd};
+
+use crate::error::IoResultExt;
+
+pub use dir::runtime_dir;
+pub use error::{Error, Result};
+pub use hook::{reset_release_error_hook, set_release_error_hook};
+pub use info::LockInfo;
+#[cfg(unix)]
+pub use instance::{InstanceMessage, Primary, SingleInstance};
+pub use lease::Lease;
+pub use list::LockEntry;
+pub use name::{AbsolutePath, LockName};
+pub use options::{LockOptions, StalePolicy};
+pub use wait::WaitStrategy;
+#[cfg(target_os = "linux")]
+pub use waiter::LockWaiter;
+
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+mod dir;
+mod error;
+mod file;
+mod hook;
+mod info;
+#[cfg(unix)]
+mod instance;
+mod lease;
+mod list;
+mod name;
+#[cfg(target_os = "linux")]
+mod notify;
+mod options;
+#[cfg(all(unix, feature = "signal"))]
+pub mod signal;
+mod sys;
+mod wait;
+#[cfg(target_os = "linux")]
+mod waiter;
 
 #[must_use]
 pub enum LockResult {
@@ -25,48 +68,256 @@ impl LockResultWithDrop {
     }
 }
 
-/// Represent a lock file. When this value is dropped, the corresponding lock file will be removed.
+/// How the lock is held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The lock is held as long as the lock file exist and the process that created it is running.
+    #[default]
+    File,
+    /// In addition to the lock file, an exclusive `flock` is held on it for the lifetime of the [`Lock`].
+    /// The kernel release it when the process die, even if the [`Lock`] was never dropped.
+    #[cfg(unix)]
+    Flock,
+}
+
+/// Whether a lock can be held by several holders at the same time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of holders can hold the lock in shared mode at the same time.
+    Shared,
+    /// Only one holder can hold the lock.
+    #[default]
+    Exclusive,
+}
+
+/// State of a lock file.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockState {
+    /// There is no lock file, or it can be reclaimed without knowing who created it.
+    Free,
+    /// The lock is held by a running process.
+    Held(LockInfo),
+    /// The lock file was left by a process that is not running anymore.
+    /// It will be reclaimed by the next holder.
+    Stale(LockInfo),
+    /// The lock file can't be read, or was not created by this crate. It is assumed to be held.
+    Unknown,
+}
+
+impl LockState {
+    /// Return true if the lock can't be acquired in this state.
+    pub fn is_held(&self) -> bool {
+        matches!(self, Self::Held(_) | Self::Unknown)
+    }
+}
+
+/// Result of [`remove_lock`] and [`break_lock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum RemoveResult {
+    /// There was no lock file.
+    NotFound,
+    /// The lock file was removed.
+    /// Contain the [`LockInfo`] of its holder, unless the file was empty.
+    Removed(Option<LockInfo>),
+    /// The lock is held by a running process, and was not removed.
+    /// Contain the [`LockInfo`] of its holder, unless it didn't write it yet.
+    Held(Option<LockInfo>),
+}
+
+impl RemoveResult {
+    /// Return true if the lock file was removed.
+    pub fn is_removed(&self) -> bool {
+        matches!(self, Self::Removed(_))
+    }
+}
+
+/// Represent a lock file. When this value is dropped, the corresponding lock file will be removed.
+///
+/// A lock has a single owner. Use [`Lock::into_shared`] to share it.
+/// A child process forked by the holder doesn't own it: dropping the lock in the child does nothing.
+#[derive(Debug)]
 #[must_use]
 pub struct Lock {
     path: PathBuf,
+    owned: file::OwnedFile,
+    /// Keep the file open when the lock is held by a kernel lock.
+    file: Option<File>,
+    mode: LockMode,
+    heartbeat: Option<lease::Heartbeat>,
+    /// Set when the lock was released with [`Lock::release`], so it is not released again on drop.
+    released: bool,
+    /// Process that acquired the lock.
+    pid: u32,
 }
 
-/// Remove the lock if exist. Return true if successfully removed, false if there was no lock.
-pub fn remove_lock<S: AsRef<str>>(name: S) -> Result<bool> {
-    let path = get_lock_path(name.as_ref())?;
+/// A [`Lock`] that can be cloned. The lock is released when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct SharedLock(Arc<Lock>);
 
-    if fs::exists(&path)? {
-        fs::remove_file(&path)?;
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+/// Remove the lock if it was acquired by the current process with `try_lock`, or left by a process that is not running anymore.
+///
+/// This is how a lock acquired with [`try_lock`] is released.
+/// A lock held by another running process, or by a [`Lock`] of the current process, is not removed, and [`RemoveResult::Held`] is returned.
+/// Files that were not created by this crate are never removed: [`Error::InvalidLockFile`] is returned.
+pub fn remove_lock<N: LockName>(name: N) -> Result<RemoveResult> {
+    LockOptions::new().remove_lock(name)
+}
+
+/// Remove the lock, even if it is held by a running process.
+///
+/// Files that were not created by this crate are never removed: [`Error::InvalidLockFile`] is returned.
+pub fn break_lock<N: LockName>(name: N) -> Result<RemoveResult> {
+    LockOptions::new().break_lock(name)
 }
 
 /// Try to acquire the lock.
-pub fn try_lock<S: AsRef<str>>(name: S) -> Result<LockResult> {
-    let path = get_lock_path(name.as_ref())?;
-    let res = create_log_file(&path)?;
-    Ok(res)
+///
+/// The [`LockInfo`] of the current process is written in the lock file.
+/// If the process that created an existing lock is not running anymore, the lock is reclaimed.
+pub fn try_lock<N: LockName>(name: N) -> Result<LockResult> {
+    let options = LockOptions::new();
+    let path = options.path(name)?;
+    options.try_lock_persistent(&path)
 }
 
-/// Return true if this name is locked.
-pub fn is_locked<S: AsRef<str>>(name: S) -> Result<bool> {
-    let path = get_lock_path(name.as_ref())?;
-    let exist = fs::exists(&path)?;
-    Ok(exist)
+/// Return true if this name is locked by a running process.
+pub fn is_locked<N: LockName>(name: N) -> Result<bool> {
+    LockOptions::new().is_locked(name)
 }
 
-/// Try to acquire the lock, and unlock when the [`Lock`] is dropped.
-pub fn try_lock_until_dropped<S: AsRef<str>>(name: S) -> Result<LockResultWithDrop> {
-    let path = get_lock_path(name.as_ref())?;
-    let res = create_log_file(&path)?;
-    let res = match res {
-        LockResult::Success => LockResultWithDrop::Locked(Lock { path }),
-        LockResult::AlreadyLocked => LockResultWithDrop::AlreadyLocked,
+/// Read the [`LockInfo`] written in the lock file.
+/// Return `None` if there is no lock file, or if its holder didn't write it yet.
+pub fn read_lock_info<N: LockName>(name: N) -> Result<Option<LockInfo>> {
+    let path = LockOptions::new().path(name)?;
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(&path, e)),
+    };
+
+    if content.is_empty() {
+        return Ok(None);
+    }
+
+    match LockInfo::parse(&content) {
+        Ok(info) => Ok(Some(info)),
+        Err(reason) => Err(Error::InvalidLockFile { path, reason }),
+    }
+}
+
+/// Return the mode in which this name is locked, or `None` if it is not locked.
+pub fn locked_mode<N: LockName>(name: N) -> Result<Option<LockMode>> {
+    let mode = match lock_state(name)? {
+        LockState::Free | LockState::Stale(_) => None,
+        LockState::Held(info) => Some(info.mode),
+        LockState::Unknown => Some(LockMode::Exclusive),
     };
-    Ok(res)
+    Ok(mode)
+}
+
+/// Return the state of this lock, telling apart a running holder from a lock file left behind.
+pub fn lock_state<N: LockName>(name: N) -> Result<LockState> {
+    LockOptions::new().lock_state(name)
+}
+
+/// Try to acquire the lock, and unlock when the [`Lock`] is dropped.
+pub fn try_lock_until_dropped<N: LockName>(name: N) -> Result<LockResultWithDrop> {
+    LockOptions::new().try_lock(name)
+}
+
+/// Try to acquire the lock using this [`Backend`], and unlock when the [`Lock`] is dropped.
+pub fn try_lock_until_dropped_with_backend<N: LockName>(
+    name: N,
+    backend: Backend,
+) -> Result<LockResultWithDrop> {
+    LockOptions::new().backend(backend).try_lock(name)
+}
+
+/// Try to acquire the lock in shared mode, and unlock when the [`Lock`] is dropped.
+///
+/// Other holders can acquire it in shared mode at the same time, but not in exclusive mode.
+/// The lock is held with the [`Backend::Flock`] backend.
+#[cfg(unix)]
+pub fn try_lock_shared<N: LockName>(name: N) -> Result<LockResultWithDrop> {
+    LockOptions::new().mode(LockMode::Shared).try_lock(name)
+}
+
+/// Try to acquire the lock in exclusive mode, and unlock when the [`Lock`] is dropped.
+///
+/// This fail if the lock is held in any mode.
+/// The lock is held with the [`Backend::Flock`] backend.
+#[cfg(unix)]
+pub fn try_lock_exclusive<N: LockName>(name: N) -> Result<LockResultWithDrop> {
+    LockOptions::new().backend(Backend::Flock).try_lock(name)
+}
+
+/// Try to acquire the lock with a [`Lease`], and unlock when the [`Lock`] is dropped.
+///
+/// Other processes consider the lock expired if it was not refreshed for longer than the ttl of the lease,
+/// whether its holder is running or not.
+pub fn try_lock_with_lease<N: LockName>(name: N, lease: Lease) -> Result<LockResultWithDrop> {
+    LockOptions::new().lease(lease).try_lock(name)
+}
+
+/// Find the lock files created by this crate in the runtime directory and its subdirectories,
+/// and their state.
+///
+/// Lock files that are not held are listed as [`LockState::Stale`].
+/// They can be removed with [`remove_lock`].
+pub fn list_locks() -> Result<Vec<LockEntry>> {
+    LockOptions::new().list_locks()
+}
+
+/// Become the primary instance of an application, or forward a message to it.
+///
+/// If the lock is free, it is acquired, and [`SingleInstance::Primary`] is returned.
+/// The primary instance receive the [`InstanceMessage`]s of the other instances on a unix socket next to the lock file.
+///
+/// Otherwise, the command line arguments, the working directory and `message` of this process are sent
+/// to the primary instance, and [`SingleInstance::Forwarded`] is returned.
+///
+/// ```no_run
+/// use alive_lock_file::{single_instance, SingleInstance};
+///
+/// let primary = match single_instance("my_app.lock", None)? {
+///     SingleInstance::Primary(primary) => primary,
+///     SingleInstance::Forwarded => return Ok(()),
+/// };
+///
+/// for message in primary.messages() {
+///     println!("opened again with {:?}", message.args);
+/// }
+/// # Ok::<(), alive_lock_file::Error>(())
+/// ```
+#[cfg(unix)]
+pub fn single_instance<N: LockName>(name: N, message: Option<&str>) -> Result<SingleInstance> {
+    LockOptions::new().single_instance(name, message)
+}
+
+/// Wait for the lock from an event loop, without blocking a thread.
+/// See [`LockWaiter`].
+#[cfg(target_os = "linux")]
+pub fn lock_waiter<N: LockName>(name: N) -> Result<LockWaiter> {
+    LockOptions::new().lock_waiter(name)
+}
+
+/// Acquire the lock, waiting for the current holder to release it.
+pub fn lock_blocking<N: LockName>(name: N) -> Result<Lock> {
+    LockOptions::new().lock(name)
+}
+
+/// Acquire the lock, waiting at most `timeout` for the current holder to release it.
+/// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held after the timeout.
+pub fn lock_timeout<N: LockName>(name: N, timeout: Duration) -> Result<LockResultWithDrop> {
+    LockOptions::new().lock_timeout(name, timeout)
+}
+
+/// Acquire the lock, waiting until `deadline` for the current holder to release it.
+/// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held at the deadline.
+pub fn lock_until<N: LockName>(name: N, deadline: Instant) -> Result<LockResultWithDrop> {
+    LockOptions::new().lock_until(name, deadline)
 }
 
 impl Lock {
@@ -74,43 +325,132 @@ impl Lock {
     pub fn path(&self) -> &Path {
         &self.path
     }
-}
 
-impl Drop for Lock {
-    fn drop(&mut self) {
-        if let Err(e) = fs::remove_file(&self.path) {
-            error!(
-                "can't remove file {} in drop lock: {e}",
-                self.path.display()
-            );
+    /// Get the mode in which this lock is held.
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    /// Get the file descriptor holding the `flock` of this lock, for locks held with [`Backend::Flock`] or in shared mode.
+    ///
+    /// A child process inheriting it keep the lock held, even after this process exit.
+    #[cfg(unix)]
+    pub fn flock_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file.as_ref().map(AsFd::as_fd)
+    }
+
+    /// Return true if the lock file was removed or replaced by another process since this lock was acquired,
+    /// for example with [`break_lock`]. The lock is not held anymore in this case.
+    pub fn is_lost(&self) -> bool {
+        !self.owned.is_at(&self.path)
+    }
+
+    /// Release the lock now, returning the error if the lock file can't be removed.
+    ///
+    /// Dropping the lock does the same, but errors are reported to the hook set with [`set_release_error_hook`].
+    ///
+    /// In a child process forked by the holder, this does nothing.
+    pub fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.unlock()
+    }
+
+    /// Convert this lock to a [`SharedLock`], that can be cloned.
+    pub fn into_shared(self) -> SharedLock {
+        SharedLock(Arc::new(self))
+    }
+
+    /// Refresh the [`Lease`] of this lock.
+    ///
+    /// This must be called more often than the ttl of a lease created with [`Lease::manual`].
+    /// Return [`Error::LockLost`] if the lock file was removed or replaced, see [`Lock::is_lost`].
+    pub fn refresh(&self) -> Result<()> {
+        lease::refresh(&self.path, &self.owned)
+    }
+
+    fn unlock(&mut self) -> Result<()> {
+        // the lock is owned by the parent of a forked process, which still hold it
+        if self.pid != process::id() {
+            // the heartbeat thread only exist in the parent, so it can't be joined
+            mem::forget(self.heartbeat.take());
+            return Ok(());
         }
+
+        #[cfg(all(unix, feature = "signal"))]
+        if !signal::unregister(&self.path) {
+            return Ok(());
+        }
+
+        // stop refreshing the file before removing it
+        drop(self.heartbeat.take());
+
+        #[cfg(unix)]
+        if self.mode == LockMode::Shared {
+            // the file is removed by the last holder, which is the only one able to lock it exclusively
+            if let Some(file) = &self.file {
+                if !sys::try_flock(file, true).with_path(&self.path)? {
+                    return Ok(());
+                }
+            }
+        }
+
+        // another process could have removed the file, and acquired the lock again
+        if self.is_lost() {
+            return Err(Error::LockLost {
+                path: self.path.clone(),
+            });
+        }
+
+        fs::remove_file(&self.path).with_path(&self.path)?;
+
+        // the kernel lock must be released only after the file is removed
+        drop(self.file.take());
+
+        Ok(())
+    }
+}
+
+impl PartialEq for Lock {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
     }
 }
 
-fn get_lock_path(name: &str) -> Result<PathBuf> {
-    if name.starts_with('/') {
-        return Ok(PathBuf::from(name));
+impl Eq for Lock {}
+
+impl SharedLock {
+    /// Release the lock if this is the last clone, returning the error if the lock file can't be removed.
+    /// Otherwise, the lock stay held by the other clones.
+    pub fn release(self) -> Result<()> {
+        match Arc::try_unwrap(self.0) {
+            Ok(lock) => lock.release(),
+            Err(_) => Ok(()),
+        }
     }
+}
 
-    let path = dirs::runtime_dir()
-        .ok_or(anyhow!("no runtime dir"))?
-        .join(name);
+impl Deref for SharedLock {
+    type Target = Lock;
 
-    Ok(path)
+    fn deref(&self) -> &Lock {
+        &self.0
+    }
 }
 
-fn create_log_file(path: &Path) -> Result<LockResult> {
-    let parents = path.parent().ok_or(anyhow!("no parent directory"))?;
+impl From<Lock> for SharedLock {
+    fn from(lock: Lock) -> Self {
+        lock.into_shared()
+    }
+}
 
-    std::fs::create_dir_all(parents)?;
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
 
-    match File::create_new(&path) {
-        Ok(_) => Ok(LockResult::Success),
-        Err(e) => {
-            if e.kind() == ErrorKind::AlreadyExists {
-                return Ok(LockResult::AlreadyLocked);
-            }
-            return Err(e.into());
+        if let Err(e) = self.unlock() {
+            hook::release_error(&e);
         }
     }
 }
@@ -0,0 +1,89 @@
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{error::IoResultExt, file, info, Error, LockState, Result};
+
+/// A lock file found by [`crate::list_locks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockEntry {
+    /// Name of the lock, relative to the lock directory.
+    pub name: String,
+    pub path: PathBuf,
+    pub state: LockState,
+}
+
+/// Find the lock files created by this crate in this directory and its subdirectories.
+pub(crate) fn list_locks(dir: &Path) -> Result<Vec<LockEntry>> {
+    let mut entries = Vec::new();
+    visit(dir, dir, &mut entries)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn visit(root: &Path, dir: &Path, entries: &mut Vec<LockEntry>) -> Result<()> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        // the runtime directory also contain directories of other programs
+        Err(e) if dir != root && e.kind() == ErrorKind::PermissionDenied => return Ok(()),
+        Err(e) => return Err(Error::io(dir, e)),
+    };
+
+    for entry in read_dir {
+        let entry = entry.with_path(dir)?;
+        let path = entry.path();
+
+        // symlinks are not followed
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            visit(root, &path, entries)?;
+            continue;
+        }
+
+        if !file_type.is_file() || is_tmp_file(&path) || !has_header(&path) {
+            continue;
+        }
+
+        let state = file::read_lock_state(&path)?;
+
+        // removed since we listed the directory
+        if state == LockState::Free {
+            continue;
+        }
+
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+
+        entries.push(LockEntry { name, path, state });
+    }
+
+    Ok(())
+}
+
+/// Return true for the files written before they are linked to the name of the lock.
+fn is_tmp_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.starts_with('.') && name.ends_with(".tmp"))
+}
+
+/// Return true if the file start with the header of the lock files.
+fn has_header(path: &Path) -> bool {
+    let mut prefix = Vec::new();
+
+    File::open(path)
+        .and_then(|file| {
+            file.take(info::HEADER.len() as u64)
+                .read_to_end(&mut prefix)
+        })
+        .is_ok_and(|_| prefix == info::HEADER.as_bytes())
+}
@@ -0,0 +1,249 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Error, LockOptions, Result};
+
+/// Maximum length of a component of a lock name, in bytes.
+/// This leave room for the temporary file name under the usual limit of 255 bytes.
+const MAX_COMPONENT_LEN: usize = 200;
+
+/// Something that can be resolved to the path of a lock file.
+///
+/// Strings are lock names, relative to the directory of the [`LockOptions`].
+/// They can contain `/` to use subdirectories, and are normalized, so `a//b` and `a/./b` are the same lock as `a/b`.
+/// Names that are empty, absolute, that contain `..`, `\` or a nul byte,
+/// or a component longer than 200 bytes are rejected with [`Error::InvalidName`].
+///
+/// An [`AbsolutePath`] must be used to create a lock file outside of this directory.
+pub trait LockName: private::Sealed {
+    /// Resolve the path of the lock file.
+    #[doc(hidden)]
+    fn lock_path(&self, options: &LockOptions) -> Result<PathBuf>;
+}
+
+/// Absolute path of a lock file, to opt-in to locks outside of the lock directory.
+///
+/// ```no_run
+/// use alive_lock_file::{try_lock_until_dropped, AbsolutePath};
+///
+/// let lock = try_lock_until_dropped(AbsolutePath::new("/var/lock/my_app.lock")?)?;
+/// # Ok::<(), alive_lock_file::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AbsolutePath(PathBuf);
+
+impl AbsolutePath {
+    /// The path must be absolute, and must not contain `..` or a nul byte.
+    /// It is normalized, like lock names.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+
+        let invalid = |reason| Error::InvalidName {
+            name: path.to_string_lossy().into_owned(),
+            reason,
+        };
+
+        if !path.is_absolute() {
+            return Err(invalid("not an absolute path"));
+        }
+
+        if path.as_os_str().as_encoded_bytes().contains(&0) {
+            return Err(invalid("contain a nul byte"));
+        }
+
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::ParentDir => return Err(invalid("contain `..`")),
+                Component::CurDir => {}
+                component => normalized.push(component),
+            }
+        }
+
+        if normalized.file_name().is_none() {
+            return Err(invalid("no file name"));
+        }
+
+        Ok(Self(normalized))
+    }
+
+    /// Get the normalized path.
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for AbsolutePath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> LockName for T {
+    fn lock_path(&self, options: &LockOptions) -> Result<PathBuf> {
+        let name = normalize_name(self.as_ref())?;
+        Ok(options.lock_dir()?.join(name))
+    }
+}
+
+impl LockName for AbsolutePath {
+    fn lock_path(&self, _options: &LockOptions) -> Result<PathBuf> {
+        Ok(self.0.clone())
+    }
+}
+
+impl LockName for &AbsolutePath {
+    fn lock_path(&self, _options: &LockOptions) -> Result<PathBuf> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Validate a relative lock name, and remove its empty and `.` components.
+fn normalize_name(name: &str) -> Result<PathBuf> {
+    let invalid = |reason| Error::InvalidName {
+        name: name.to_owned(),
+        reason,
+    };
+
+    if name.starts_with('/') {
+        return Err(invalid("absolute path, use `AbsolutePath` to opt-in"));
+    }
+
+    if name.contains('\0') {
+        return Err(invalid("contain a nul byte"));
+    }
+
+    // this is a path separator on windows
+    if name.contains('\\') {
+        return Err(invalid("contain `\\`"));
+    }
+
+    let mut normalized = PathBuf::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return Err(invalid("contain `..`")),
+            component if component.len() > MAX_COMPONENT_LEN => {
+                return Err(invalid("component too long"))
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        return Err(invalid("empty name"));
+    }
+
+    Ok(normalized)
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl<T: AsRef<str> + ?Sized> Sealed for T {}
+    impl Sealed for super::AbsolutePath {}
+    impl Sealed for &super::AbsolutePath {}
+}
+
+// the expected paths are unix paths
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn options() -> LockOptions {
+        let mut options = LockOptions::new();
+        options.dir("/locks");
+        options
+    }
+
+    #[test]
+    fn names_are_normalized() {
+        for name in [
+            "a/b",
+            "a//b",
+            "a/./b",
+            "./a/b",
+            "a/b/",
+            "a/b/.",
+            "a///./b//",
+        ] {
+            assert_eq!(
+                options().path(name).unwrap(),
+                Path::new("/locks/a/b"),
+                "{name:?}"
+            );
+        }
+
+        assert_eq!(options().path("lock").unwrap(), Path::new("/locks/lock"));
+        assert_eq!(
+            options().path("my.lock").unwrap(),
+            Path::new("/locks/my.lock")
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        let long = "a".repeat(MAX_COMPONENT_LEN + 1);
+
+        for (name, reason) in [
+            ("", "empty name"),
+            (".", "empty name"),
+            ("./", "empty name"),
+            ("//", "absolute path, use `AbsolutePath` to opt-in"),
+            ("/etc/passwd", "absolute path, use `AbsolutePath` to opt-in"),
+            ("..", "contain `..`"),
+            ("../a", "contain `..`"),
+            ("a/../../b", "contain `..`"),
+            ("a/..", "contain `..`"),
+            ("a\0b", "contain a nul byte"),
+            ("a\\b", "contain `\\`"),
+            ("..\\a", "contain `\\`"),
+            (&long, "component too long"),
+            (&format!("a/{long}"), "component too long"),
+        ] {
+            match options().path(name) {
+                Err(Error::InvalidName { name: n, reason: r }) => {
+                    assert_eq!((n.as_str(), r), (name, reason));
+                }
+                res => panic!("{name:?}: {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn component_length_limit() {
+        let name = "a".repeat(MAX_COMPONENT_LEN);
+        assert!(options().path(&name).is_ok());
+        // dots are allowed inside components
+        assert!(options().path("a..b/.hidden").is_ok());
+    }
+
+    #[test]
+    fn absolute_paths() {
+        for path in ["/a/b", "/a//b", "/a/./b", "/a/b/", "/a/b/."] {
+            let path = AbsolutePath::new(path).unwrap();
+            assert_eq!(path.as_path(), Path::new("/a/b"));
+            // the directory of the options is not used
+            assert_eq!(options().path(&path).unwrap(), Path::new("/a/b"));
+        }
+    }
+
+    #[test]
+    fn invalid_absolute_paths() {
+        for (path, reason) in [
+            ("a/b", "not an absolute path"),
+            ("", "not an absolute path"),
+            ("/a/../b", "contain `..`"),
+            ("/a/b/..", "contain `..`"),
+            ("/a\0b", "contain a nul byte"),
+            ("/", "no file name"),
+            ("/.", "no file name"),
+        ] {
+            match AbsolutePath::new(path) {
+                Err(Error::InvalidName { name, reason: r }) => {
+                    assert_eq!((name.as_str(), r), (path, reason));
+                }
+                res => panic!("{path:?}: {res:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Wake up waiters when a lock file is removed, with inotify,
+//! or when the process holding it exit, with a pidfd (linux only).
+
+use std::{
+    ffi::{CString, OsString},
+    io,
+    mem::size_of,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{file, sys, wait, LockInfo, LockState};
+
+/// Watch the directory of a lock file for the removal of this file.
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    fd: OwnedFd,
+    name: OsString,
+}
+
+impl Watcher {
+    /// Return `None` if the directory can't be watched, for example on filesystems that don't support inotify.
+    /// The caller should poll in this case.
+    pub fn new(path: &Path) -> Option<Self> {
+        let dir = path.parent()?;
+        let name = path.file_name()?.to_owned();
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // a lock file is released when it is removed, or renamed by a tool cleaning the directory
+        let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mask = libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_DELETE_SELF;
+        let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) };
+        if wd < 0 {
+            return None;
+        }
+
+        Some(Self { fd, name })
+    }
+
+    /// Read the pending events.
+    /// Return true if one of them is the removal of the lock file.
+    pub fn read_events(&self) -> io::Result<bool> {
+        let mut removed = false;
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let len =
+                unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+
+            if len < 0 {
+                let e = io::Error::last_os_error();
+                return match e.kind() {
+                    io::ErrorKind::WouldBlock => Ok(removed),
+                    _ => Err(e),
+                };
+            }
+
+            let events = &buf[..len as usize];
+            let mut offset = 0;
+
+            while offset + size_of::<libc::inotify_event>() <= events.len() {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(events[offset..].as_ptr().cast()) };
+                let name_start = offset + size_of::<libc::inotify_event>();
+                let name_end = (name_start + event.len as usize).min(events.len());
+
+                // the name is padded with nul bytes
+                let name = &events[name_start..name_end];
+                let name = name.split(|&b| b == 0).next().unwrap_or_default();
+
+                if event.mask & (libc::IN_DELETE_SELF | libc::IN_Q_OVERFLOW | libc::IN_IGNORED) != 0
+                    || name == self.name.as_bytes()
+                {
+                    removed = true;
+                }
+
+                offset = name_end;
+            }
+        }
+    }
+}
+
+impl AsFd for Watcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Process holding a lock.
+#[derive(Debug)]
+pub(crate) struct Holder {
+    pid: u32,
+    start_time: Option<u64>,
+    pidfd: OwnedFd,
+}
+
+impl Holder {
+    /// Open the process holding the lock file, reusing `previous` if it still hold it.
+    /// Return `None` if the lock is not held by a process of this host that can be waited for.
+    pub fn of_lock(path: &Path, previous: Option<Self>) -> Option<Self> {
+        let Ok(LockState::Held(info)) = file::read_lock_state(path) else {
+            return None;
+        };
+
+        match previous {
+            Some(holder) if holder.pid == info.pid && holder.start_time == info.start_time => {
+                Some(holder)
+            }
+            _ => Self::open(&info),
+        }
+    }
+
+    /// Return true if the process exited, without blocking.
+    pub fn has_exited(&self) -> bool {
+        let mut fd = libc::pollfd {
+            fd: self.pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut fd, 1, 0) > 0 }
+    }
+
+    fn open(info: &LockInfo) -> Option<Self> {
+        // the pid of a holder using a lease can belong to another pid namespace
+        if info.lease_ttl.is_some()
+            || info.hostname != sys::hostname()
+            || info.boot_id != sys::boot_id()
+        {
+            return None;
+        }
+
+        let pid = libc::pid_t::try_from(info.pid).ok()?;
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if fd < 0 {
+            // the process exited, or pidfds are not supported by this kernel
+            return None;
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        // the pid could have been reused before we opened it
+        if info.start_time.is_some() && sys::start_time(info.pid) != info.start_time {
+            return None;
+        }
+
+        Some(Self {
+            pid: info.pid,
+            start_time: info.start_time,
+            pidfd,
+        })
+    }
+}
+
+impl AsFd for Holder {
+    /// The pidfd is readable once the process exited.
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.pidfd.as_fd()
+    }
+}
+
+/// Wait at most `timeout` for the removal of the lock file, or the exit of its holder.
+/// Sleep for `timeout` if there is nothing to watch.
+///
+/// Events received since the last call are not lost.
+pub(crate) fn wait(watcher: Option<&Watcher>, holder: Option<&Holder>, timeout: Duration) {
+    let mut fds: Vec<_> = watcher
+        .map(|watcher| watcher.fd.as_raw_fd())
+        .into_iter()
+        .chain(holder.map(|holder| holder.pidfd.as_raw_fd()))
+        .map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    if fds.is_empty() {
+        thread::sleep(timeout);
+        return;
+    }
+
+    let deadline = wait::deadline_after(Instant::now(), timeout);
+
+    loop {
+        let timeout = deadline.map_or(timeout, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        // round up, to not spin when the timeout is less than a millisecond
+        let timeout_ms = timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32;
+
+        // a timeout, or an interruption by a signal, is handled like an event: the caller try again
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+        if ready <= 0 || holder.is_some_and(Holder::has_exited) {
+            return;
+        }
+
+        // consume the events, so the next call doesn't return immediately.
+        // The directory also contain other lock files, and the temporary files of our own attempts,
+        // waking up for them would retry in a busy loop
+        match watcher.map(Watcher::read_events) {
+            Some(Ok(false)) => {}
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use crate::file::OwnedFile;
+#[cfg(target_os = "linux")]
+use crate::LockWaiter;
+use crate::{
+    file, list, runtime_dir, wait, Backend, Error, Lease, Lock, LockEntry, LockInfo, LockMode,
+    LockName, LockResult, LockResultWithDrop, LockState, RemoveResult, Result, WaitStrategy,
+};
+#[cfg(unix)]
+use crate::{instance, SingleInstance};
+
+/// What to do with a lock file left by a holder that is not running anymore.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StalePolicy {
+    /// Remove it and acquire the lock.
+    #[default]
+    Reclaim,
+    /// Consider the lock as held. It must be removed with [`crate::remove_lock`].
+    Keep,
+}
+
+/// Options used to acquire a lock, configured once and reused for any number of lock names.
+///
+/// ```no_run
+/// use alive_lock_file::{Backend, LockOptions, LockResultWithDrop};
+///
+/// let lock = LockOptions::new()
+///     .dir("/var/lock/my_app")
+///     .backend(Backend::Flock)
+///     .try_lock("database.lock")?;
+///
+/// if let LockResultWithDrop::Locked(lock) = lock {
+///     println!("locked {}", lock.path().display());
+/// }
+/// # Ok::<(), alive_lock_file::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LockOptions {
+    pub(crate) dir: Option<PathBuf>,
+    pub(crate) file_mode: Option<u32>,
+    pub(crate) backend: Backend,
+    pub(crate) mode: LockMode,
+    pub(crate) stale_policy: StalePolicy,
+    pub(crate) wait_strategy: WaitStrategy,
+    pub(crate) lease: Option<Lease>,
+}
+
+impl LockOptions {
+    /// Default options: exclusive locks held with the [`Backend::File`] backend, in the runtime directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the directory in which lock files are created.
+    /// Default to [`runtime_dir`].
+    pub fn dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Set the permissions of the lock files, before the umask of the process is applied.
+    #[cfg(unix)]
+    pub fn file_mode(&mut self, file_mode: u32) -> &mut Self {
+        self.file_mode = Some(file_mode);
+        self
+    }
+
+    /// Set the [`Backend`] used to hold the lock.
+    pub fn backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set the [`LockMode`]. Shared locks are always held with the [`Backend::Flock`] backend.
+    pub fn mode(&mut self, mode: LockMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set what to do with stale lock files.
+    pub fn stale_policy(&mut self, stale_policy: StalePolicy) -> &mut Self {
+        self.stale_policy = stale_policy;
+        self
+    }
+
+    /// Set how to wait for a lock held by another process.
+    pub fn wait_strategy(&mut self, wait_strategy: WaitStrategy) -> &mut Self {
+        self.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// Hold the lock with a [`Lease`].
+    pub fn lease(&mut self, lease: Lease) -> &mut Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Get the path of the lock file for this name.
+    ///
+    /// See [`LockName`] for the names that are accepted.
+    pub fn path<N: LockName>(&self, name: N) -> Result<PathBuf> {
+        name.lock_path(self)
+    }
+
+    /// Try to acquire the lock, and unlock when the [`Lock`] is dropped.
+    pub fn try_lock<N: LockName>(&self, name: N) -> Result<LockResultWithDrop> {
+        let path = self.path(name)?;
+        self.try_lock_path(&path)
+    }
+
+    /// Acquire the lock, waiting for the current holder to release it.
+    pub fn lock<N: LockName>(&self, name: N) -> Result<Lock> {
+        let path = self.path(name)?;
+        let lock = wait::wait_for_lock(self.wait_strategy, None, &path, || {
+            self.try_lock_path(&path)
+        })?;
+        Ok(lock.expect("waiting without deadline always return a lock"))
+    }
+
+    /// Acquire the lock, waiting at most `timeout` for the current holder to release it.
+    /// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held after the timeout.
+    pub fn lock_timeout<N: LockName>(
+        &self,
+        name: N,
+        timeout: Duration,
+    ) -> Result<LockResultWithDrop> {
+        let path = self.path(name)?;
+        self.lock_path_until(&path, wait::deadline_after(Instant::now(), timeout))
+    }
+
+    /// Acquire the lock, waiting until `deadline` for the current holder to release it.
+    /// Return [`LockResultWithDrop::AlreadyLocked`] if it is still held at the deadline.
+    pub fn lock_until<N: LockName>(
+        &self,
+        name: N,
+        deadline: Instant,
+    ) -> Result<LockResultWithDrop> {
+        let path = self.path(name)?;
+        self.lock_path_until(&path, Some(deadline))
+    }
+
+    /// Wait for the lock from an event loop, without blocking a thread.
+    /// See [`LockWaiter`].
+    #[cfg(target_os = "linux")]
+    pub fn lock_waiter<N: LockName>(&self, name: N) -> Result<LockWaiter> {
+        let path = self.path(name)?;
+        LockWaiter::new(self, path)
+    }
+
+    /// Return the state of this lock, telling apart a running holder from a lock file left behind.
+    pub fn lock_state<N: LockName>(&self, name: N) -> Result<LockState> {
+        let path = self.path(name)?;
+        file::read_lock_state(&path)
+    }
+
+    /// Return true if this name is locked by a running process.
+    pub fn is_locked<N: LockName>(&self, name: N) -> Result<bool> {
+        let state = self.lock_state(name)?;
+        Ok(state.is_held())
+    }
+
+    /// Become the primary instance of an application, or forward a message to it.
+    /// See [`crate::single_instance`].
+    #[cfg(unix)]
+    pub fn single_instance<N: LockName>(
+        &self,
+        name: N,
+        message: Option<&str>,
+    ) -> Result<SingleInstance> {
+        let path = self.path(name)?;
+        instance::single_instance(self, &path, message)
+    }
+
+    /// Remove the lock if it was acquired by the current process with `try_lock`, or left by a process that is not running anymore.
+    /// See [`crate::remove_lock`].
+    pub fn remove_lock<N: LockName>(&self, name: N) -> Result<RemoveResult> {
+        let path = self.path(name)?;
+        file::remove_lock_file(&path, false)
+    }
+
+    /// Remove the lock, even if it is held by a running process.
+    /// See [`crate::break_lock`].
+    pub fn break_lock<N: LockName>(&self, name: N) -> Result<RemoveResult> {
+        let path = self.path(name)?;
+        file::remove_lock_file(&path, true)
+    }
+
+    /// Find the lock files created by this crate in the lock directory, and their state.
+    /// See [`crate::list_locks`].
+    pub fn list_locks(&self) -> Result<Vec<LockEntry>> {
+        list::list_locks(&self.lock_dir()?)
+    }
+
+    /// Directory in which the lock files are created.
+    pub(crate) fn lock_dir(&self) -> Result<PathBuf> {
+        match &self.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => runtime_dir(),
+        }
+    }
+
+    /// Info written in the lock file when it is acquired with these options.
+    pub(crate) fn lock_info(&self) -> LockInfo {
+        let mut info = LockInfo::current(self.mode);
+        info.lease_ttl = self.lease.map(|lease| lease.ttl());
+        info
+    }
+
+    /// Try to acquire the lock, without removing it when this process stop.
+    pub(crate) fn try_lock_persistent(&self, path: &Path) -> Result<LockResult> {
+        let res = match file::create_log_file(path, self)? {
+            Some(owned) => {
+                file::add_persistent_lock(path, owned);
+                LockResult::Success
+            }
+            None => LockResult::AlreadyLocked,
+        };
+        Ok(res)
+    }
+
+    pub(crate) fn try_lock_path(&self, path: &Path) -> Result<LockResultWithDrop> {
+        let mode = self.mode;
+
+        #[cfg(unix)]
+        if self.backend == Backend::Flock || mode == LockMode::Shared {
+            let res = match file::create_flock_file(path, self)? {
+                Some(file) => LockResultWithDrop::Locked(self.new_lock(
+                    path,
+                    OwnedFile::open(path, &file)?,
+                    Some(file),
+                )),
+                None => LockResultWithDrop::AlreadyLocked,
+            };
+            return Ok(res);
+        }
+
+        if mode == LockMode::Shared {
+            return Err(Error::Unsupported("shared locks"));
+        }
+
+        let res = match file::create_log_file(path, self)? {
+            Some(owned) => LockResultWithDrop::Locked(self.new_lock(path, owned, None)),
+            None => LockResultWithDrop::AlreadyLocked,
+        };
+        Ok(res)
+    }
+
+    /// Acquire the lock, waiting until `deadline`, or forever if there is none.
+    fn lock_path_until(
+        &self,
+        path: &Path,
+        deadline: Option<Instant>,
+    ) -> Result<LockResultWithDrop> {
+        let lock = wait::wait_for_lock(self.wait_strategy, deadline, path, || {
+            self.try_lock_path(path)
+        })?;
+        let res = match lock {
+            Some(lock) => LockResultWithDrop::Locked(lock),
+            None => LockResultWithDrop::AlreadyLocked,
+        };
+        Ok(res)
+    }
+
+    /// Create the [`Lock`] for a lock file acquired with these options.
+    fn new_lock(&self, path: &Path, owned: OwnedFile, file: Option<File>) -> Lock {
+        // other processes can hold a shared lock, so its file must not be removed when this process terminate
+        #[cfg(all(unix, feature = "signal"))]
+        if self.mode == LockMode::Exclusive {
+            crate::signal::register(path, &owned);
+        }
+
+        let heartbeat = self
+            .lease
+            .and_then(|lease| lease.spawn_heartbeat(path, &owned));
+
+        Lock {
+            path: path.to_path_buf(),
+            owned,
+            file,
+            mode: self.mode,
+            heartbeat,
+            released: false,
+            pid: process::id(),
+        }
+    }
+}
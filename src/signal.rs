@@ -0,0 +1,128 @@
+//! Remove the lock files of this process when it is killed by a signal, or when it panics.
+//!
+//! A process terminated by a signal like `SIGINT` (Ctrl-C) or `SIGTERM` never drop its [`Lock`]s,
+//! so their lock files are left behind. They are reclaimed by the next holder if its pid can be checked,
+//! but not with [`StalePolicy::Keep`], or when the holder was running in another pid namespace.
+//!
+//! After [`install`] is called, every lock held in exclusive mode by this process is registered,
+//! and its lock file is removed when the process receive `SIGINT`, `SIGTERM`, `SIGQUIT` or `SIGHUP`.
+//! The signal is then raised again with its default action, so the process terminate like it would have.
+//!
+//! Lock files are also removed by the panic hook when the panic strategy is `abort`.
+//! Otherwise the panic unwind, and the locks are released when they are dropped.
+//! The hook can't remove them in this case, since the panic can be caught.
+//!
+//! Locks held in shared mode are not registered, since other processes can hold them.
+//! They are held with a `flock`, released by the kernel when the process die.
+//!
+//! [`Lock`]: crate::Lock
+//! [`StalePolicy::Keep`]: crate::StalePolicy::Keep
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    thread,
+};
+
+use signal_hook::{
+    consts::{SIGHUP, TERM_SIGNALS},
+    iterator::Signals,
+    low_level,
+};
+
+use crate::{file::OwnedFile, Error, Result};
+
+/// `None` until the handlers are installed.
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Registry {
+    /// Lock files of this process, and the files created by its locks.
+    locks: HashMap<PathBuf, OwnedFile>,
+    /// Lock files removed because the process is terminating, while their [`crate::Lock`] is still alive.
+    removed: HashSet<PathBuf>,
+}
+
+/// Install the signal handlers and the panic hook.
+/// Only the locks acquired after this call are removed.
+///
+/// Calling it more than once does nothing.
+pub fn install() -> Result<()> {
+    let mut registry = registry();
+    if registry.is_some() {
+        return Ok(());
+    }
+
+    install_handlers().map_err(Error::Signal)?;
+    *registry = Some(Registry::default());
+
+    Ok(())
+}
+
+fn install_handlers() -> std::io::Result<()> {
+    let mut signals = Signals::new(TERM_SIGNALS.iter().chain([&SIGHUP]))?;
+
+    thread::Builder::new()
+        .name("alive_lock_file signals".to_owned())
+        .spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                remove_registered();
+                if let Err(e) = low_level::emulate_default_handler(signal) {
+                    log::error!("can't raise signal {signal}: {e}");
+                }
+            }
+        })?;
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // otherwise the locks are released when they are dropped while unwinding,
+        // and the ones still alive are still held if the panic is caught
+        if cfg!(panic = "abort") {
+            remove_registered();
+        }
+        previous(info);
+    }));
+
+    Ok(())
+}
+
+/// Register a lock file, to remove it on signals, if the handlers are installed.
+pub(crate) fn register(path: &Path, owned: &OwnedFile) {
+    if let Some(registry) = registry().as_mut() {
+        registry.locks.insert(path.to_path_buf(), owned.clone());
+    }
+}
+
+/// Unregister a lock file before it is released.
+/// Return false if it was already removed, because the process is terminating.
+pub(crate) fn unregister(path: &Path) -> bool {
+    match registry().as_mut() {
+        Some(registry) => registry.locks.remove(path).is_some() || !registry.removed.remove(path),
+        None => true,
+    }
+}
+
+/// Remove the registered lock files that are still ours.
+fn remove_registered() {
+    let mut registry = registry();
+    let Some(registry) = registry.as_mut() else {
+        return;
+    };
+
+    for (path, owned) in registry.locks.drain() {
+        if owned.is_at(&path) {
+            if let Err(e) = fs::remove_file(&path) {
+                log::error!("can't remove file {}: {e}", path.display());
+            }
+        }
+        registry.removed.insert(path);
+    }
+}
+
+fn registry() -> MutexGuard<'static, Option<Registry>> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,158 @@
+/// Return true if a process with this pid is running.
+#[cfg(unix)]
+pub fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    if pid <= 0 {
+        return false;
+    }
+
+    // signal 0 only check that the process exist and that we could signal it
+    let res = unsafe { libc::kill(pid, 0) };
+
+    res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Start time of the process with this pid, in clock ticks since boot.
+///
+/// Two processes that got the same pid have different start time, which is used to detect pid reuse.
+/// The start time of a zombie process is not returned, because it is not running anymore.
+#[cfg(target_os = "linux")]
+pub fn start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+    // the second field is the command name between parentheses, which can contain spaces
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace();
+
+    if fields.next()? == "Z" {
+        return None;
+    }
+
+    // starttime is the 22th field, and we already consumed the 3th
+    fields.nth(18)?.parse().ok()
+}
+
+/// Start time of the process with this pid.
+///
+/// This is only available on linux.
+#[cfg(not(target_os = "linux"))]
+pub fn start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Return true if a process with this pid is running.
+///
+/// There is no portable way to check it on this platform, so the holder is always assumed to be alive.
+#[cfg(not(unix))]
+pub fn is_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Try to take a shared or exclusive `flock` on this file, without blocking.
+/// Return false if another open file description already hold a conflicting lock on it.
+///
+/// The lock is released by the kernel when the file is closed, including when the process die.
+#[cfg(unix)]
+pub fn try_flock(file: &std::fs::File, exclusive: bool) -> std::io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let operation = if exclusive {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_SH
+    };
+
+    let res = unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) };
+
+    if res == 0 {
+        return Ok(true);
+    }
+
+    let e = std::io::Error::last_os_error();
+    if e.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+/// Return true if both metadata refer to the same file.
+#[cfg(unix)]
+pub fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Identity of a file: its device and inode numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(unix)]
+pub fn file_id(metadata: &std::fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(FileId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &std::fs::Metadata) -> Option<FileId> {
+    None
+}
+
+/// Name of this host.
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if res != 0 {
+        return None;
+    }
+
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Name of this host.
+#[cfg(not(unix))]
+pub fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Real user id of this process.
+#[cfg(unix)]
+pub fn uid() -> Option<u32> {
+    Some(unsafe { libc::getuid() })
+}
+
+/// Real user id of this process.
+#[cfg(not(unix))]
+pub fn uid() -> Option<u32> {
+    None
+}
+
+/// Identifier of the current boot, which change each time the system is restarted.
+pub fn boot_id() -> Option<String> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+
+    let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(boot_id.trim().to_string())
+}
+
+/// Effective user id of this process, which own the files it create.
+#[cfg(unix)]
+pub fn euid() -> u32 {
+    unsafe { libc::geteuid() }
+}
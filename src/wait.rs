@@ -0,0 +1,118 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+use crate::notify;
+use crate::{Lock, LockResultWithDrop, Result};
+
+/// How to wait for a lock held by another process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Retry at a fixed interval.
+    Poll(Duration),
+    /// Retry with a delay doubling after each attempt, from `initial` up to `max`.
+    Backoff { initial: Duration, max: Duration },
+}
+
+impl WaitStrategy {
+    /// Shortest delay between two attempts. Shorter delays are rounded up to it,
+    /// so a zero delay doesn't retry in a busy loop.
+    pub const MIN_DELAY: Duration = Duration::from_micros(100);
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        Self::Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Delay between two attempts to acquire a lock.
+#[derive(Debug)]
+pub(crate) struct Backoff {
+    delay: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(strategy: WaitStrategy) -> Self {
+        let (delay, max) = match strategy {
+            WaitStrategy::Poll(interval) => (interval, interval),
+            WaitStrategy::Backoff { initial, max } => (initial, max),
+        };
+        Self {
+            delay: delay.max(WaitStrategy::MIN_DELAY),
+            max: max.max(WaitStrategy::MIN_DELAY),
+        }
+    }
+
+    /// Return the delay to wait before the next attempt, without exceeding the deadline.
+    pub fn next_delay(&mut self, deadline: Option<Instant>) -> Duration {
+        let mut delay = self.delay;
+        self.delay = self.delay.saturating_mul(2).min(self.max);
+
+        if let Some(deadline) = deadline {
+            delay = delay.min(deadline.saturating_duration_since(Instant::now()));
+        }
+
+        delay
+    }
+}
+
+/// Return the deadline of a wait of `timeout` starting at `start`.
+/// A timeout too large to be represented is waiting forever: `None` is returned.
+pub(crate) fn deadline_after(start: Instant, timeout: Duration) -> Option<Instant> {
+    start.checked_add(timeout)
+}
+
+/// Call `try_lock` until it succeed or the deadline is reached.
+/// Wait forever if there is no deadline.
+///
+/// On linux, the directory of the lock file is watched with inotify, and the holder with a pidfd,
+/// so waiters wake up as soon as the lock file is removed, or its holder exit.
+/// The [`WaitStrategy`] still bound the delay between two attempts,
+/// since inotify is not supported by every filesystem, and the holder can run on another host.
+pub(crate) fn wait_for_lock<F>(
+    strategy: WaitStrategy,
+    deadline: Option<Instant>,
+    path: &Path,
+    mut try_lock: F,
+) -> Result<Option<Lock>>
+where
+    F: FnMut() -> Result<LockResultWithDrop>,
+{
+    let mut backoff = Backoff::new(strategy);
+
+    // the watch is set up before the first attempt, so a removal right after it is not missed
+    #[cfg(target_os = "linux")]
+    let watcher = notify::Watcher::new(path);
+    #[cfg(target_os = "linux")]
+    let mut holder = None;
+    #[cfg(not(target_os = "linux"))]
+    let _ = path;
+
+    loop {
+        if let LockResultWithDrop::Locked(lock) = try_lock()? {
+            return Ok(Some(lock));
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(None);
+        }
+
+        let delay = backoff.next_delay(deadline);
+
+        // a holder killed with SIGKILL leave its lock file behind, it is reclaimed by the next attempt
+        #[cfg(target_os = "linux")]
+        {
+            holder = notify::Holder::of_lock(path, holder);
+            notify::wait(watcher.as_ref(), holder.as_ref(), delay);
+        }
+        #[cfg(not(target_os = "linux"))]
+        std::thread::sleep(delay);
+    }
+}
@@ -0,0 +1,199 @@
+//! Wait for a lock from an event loop, with a file descriptor readable when the lock may be acquirable (linux only).
+
+use std::{
+    io, mem,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    ptr,
+    time::Duration,
+};
+
+use crate::{
+    notify::{Holder, Watcher},
+    wait::Backoff,
+    Error, Lock, LockOptions, LockResultWithDrop, Result,
+};
+
+/// Wait for a lock without blocking a thread, for event loops like `mio`, `calloop` or a plain `poll`.
+///
+/// The file descriptor of the waiter becomes readable when the lock may be acquirable:
+/// when the lock file is removed, when its holder exit, or when the delay of the [`crate::WaitStrategy`] elapsed.
+/// [`LockWaiter::try_complete`] must then be called to try to acquire the lock.
+/// It is readable right away, so the first attempt is made on the first wake up.
+///
+/// ```no_run
+/// use std::os::fd::AsRawFd;
+///
+/// let mut waiter = alive_lock_file::lock_waiter("my_app.lock")?;
+///
+/// let lock = loop {
+///     let mut fds = [libc::pollfd { fd: waiter.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+///     unsafe { libc::poll(fds.as_mut_ptr(), 1, -1) };
+///
+///     if let Some(lock) = waiter.try_complete()? {
+///         break lock;
+///     }
+/// };
+/// # Ok::<(), alive_lock_file::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct LockWaiter {
+    options: LockOptions,
+    path: PathBuf,
+    /// Readable when one of the file descriptors below is.
+    epoll: OwnedFd,
+    /// Bound the delay between two attempts, like when waiting with [`crate::lock_blocking`].
+    timer: OwnedFd,
+    watcher: Option<Watcher>,
+    holder: Option<Holder>,
+    backoff: Backoff,
+}
+
+impl LockWaiter {
+    pub(crate) fn new(options: &LockOptions, path: PathBuf) -> Result<Self> {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        let epoll = owned_fd(epoll).map_err(|e| Error::io(&path, e))?;
+
+        let timer = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        let timer = owned_fd(timer).map_err(|e| Error::io(&path, e))?;
+
+        let waiter = Self {
+            options: options.clone(),
+            backoff: Backoff::new(options.wait_strategy),
+            epoll,
+            timer,
+            watcher: Watcher::new(&path),
+            holder: None,
+            path,
+        };
+
+        waiter.add(waiter.timer.as_raw_fd())?;
+        if let Some(watcher) = &waiter.watcher {
+            waiter.add(watcher.as_fd().as_raw_fd())?;
+        }
+
+        // a zero delay would disarm the timer
+        waiter.arm_timer(Duration::from_nanos(1))?;
+
+        Ok(waiter)
+    }
+
+    /// Get the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Try to acquire the lock, once the file descriptor of the waiter is readable.
+    ///
+    /// Return `None` if the lock is still held. The file descriptor becomes readable again
+    /// when another attempt should be made. It doesn't block, and can be called at any time.
+    pub fn try_complete(&mut self) -> Result<Option<Lock>> {
+        // consume the events, so the file descriptor is not readable until the next one
+        let removed = match &self.watcher {
+            Some(watcher) => watcher.read_events().unwrap_or(true),
+            None => false,
+        };
+        let mut expirations = 0u64;
+        let expired = unsafe {
+            libc::read(
+                self.timer.as_raw_fd(),
+                ptr::addr_of_mut!(expirations).cast(),
+                mem::size_of::<u64>(),
+            )
+        } > 0;
+        let exited = self.holder.as_ref().is_some_and(Holder::has_exited);
+
+        // like `notify::wait`, other files of the directory don't trigger an attempt
+        if !(removed || expired || exited) {
+            return Ok(None);
+        }
+
+        if let LockResultWithDrop::Locked(lock) = self.options.try_lock_path(&self.path)? {
+            return Ok(Some(lock));
+        }
+
+        // a pidfd stay readable once its process exited, it is replaced by the pidfd of the new holder
+        if let Some(holder) = &self.holder {
+            self.delete(holder.as_fd().as_raw_fd())?;
+        }
+        self.holder = Holder::of_lock(&self.path, self.holder.take());
+        if let Some(holder) = &self.holder {
+            self.add(holder.as_fd().as_raw_fd())?;
+        }
+
+        let delay = self.backoff.next_delay(None);
+        self.arm_timer(delay)?;
+
+        Ok(None)
+    }
+
+    fn add(&self, fd: RawFd) -> Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: fd as u64,
+        };
+        let res =
+            unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) };
+        self.check(res)
+    }
+
+    fn delete(&self, fd: RawFd) -> Result<()> {
+        let res = unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                ptr::null_mut(),
+            )
+        };
+        self.check(res)
+    }
+
+    /// Make the timer expire once, after `delay`.
+    fn arm_timer(&self, delay: Duration) -> Result<()> {
+        let value = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: delay.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+                tv_nsec: delay.subsec_nanos() as libc::c_long,
+            },
+        };
+        let res =
+            unsafe { libc::timerfd_settime(self.timer.as_raw_fd(), 0, &value, ptr::null_mut()) };
+        self.check(res)
+    }
+
+    fn check(&self, res: libc::c_int) -> Result<()> {
+        if res < 0 {
+            return Err(Error::io(&self.path, io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+impl AsFd for LockWaiter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.epoll.as_fd()
+    }
+}
+
+impl AsRawFd for LockWaiter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll.as_raw_fd()
+    }
+}
+
+fn owned_fd(fd: libc::c_int) -> io::Result<OwnedFd> {
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
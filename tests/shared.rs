@@ -0,0 +1,102 @@
+//! Readers acquiring a shared lock at the same time all get it.
+#![cfg(unix)]
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::{Arc, Barrier},
+    thread,
+};
+
+use alive_lock_file::{Backend, LockMode, LockOptions, LockResultWithDrop};
+
+const READERS: usize = 8;
+const ROUNDS: usize = 50;
+
+fn options(test: &str) -> (LockOptions, PathBuf) {
+    let dir = env::temp_dir().join(format!("alive_lock_file_{test}_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut options = LockOptions::new();
+    options.dir(&dir).mode(LockMode::Shared);
+    (options, dir)
+}
+
+#[test]
+fn concurrent_readers() {
+    let (options, dir) = options("shared_readers");
+
+    for round in 0..ROUNDS {
+        let name = format!("round_{round}.lock");
+        let barrier = Arc::new(Barrier::new(READERS));
+
+        let readers: Vec<_> = (0..READERS)
+            .map(|reader| {
+                let options = options.clone();
+                let name = name.clone();
+                let barrier = barrier.clone();
+
+                thread::spawn(move || {
+                    barrier.wait();
+                    let lock = options.try_lock(&name).unwrap();
+                    let mut locked = matches!(lock, LockResultWithDrop::Locked(_));
+
+                    // half of the readers release the lock while the others acquire it again
+                    barrier.wait();
+                    if reader % 2 == 0 {
+                        drop(lock);
+                    } else {
+                        let again = options.try_lock(&name).unwrap();
+                        locked &= matches!(again, LockResultWithDrop::Locked(_));
+                    }
+                    locked
+                })
+            })
+            .collect();
+
+        let locked = readers
+            .into_iter()
+            .map(|reader| reader.join().unwrap())
+            .filter(|locked| *locked)
+            .count();
+
+        assert_eq!(locked, READERS, "round {round}");
+        // the last readers can release at the same time, and leave a stale file behind
+        assert!(!options.is_locked(&name).unwrap(), "round {round}");
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writer_excludes_readers() {
+    let (options, dir) = options("shared_writer");
+    let mut writer = options.clone();
+    writer.mode(LockMode::Exclusive).backend(Backend::Flock);
+
+    let LockResultWithDrop::Locked(reader) = options.try_lock("rw.lock").unwrap() else {
+        panic!("the lock is free");
+    };
+    let LockResultWithDrop::Locked(other) = options.try_lock("rw.lock").unwrap() else {
+        panic!("shared locks can be held together");
+    };
+    assert!(matches!(
+        writer.try_lock("rw.lock").unwrap(),
+        LockResultWithDrop::AlreadyLocked
+    ));
+
+    drop(reader);
+    drop(other);
+
+    let LockResultWithDrop::Locked(lock) = writer.try_lock("rw.lock").unwrap() else {
+        panic!("the readers released the lock");
+    };
+    assert!(matches!(
+        options.try_lock("rw.lock").unwrap(),
+        LockResultWithDrop::AlreadyLocked
+    ));
+    drop(lock);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,170 @@
+//! Many processes try to reclaim the lock of a dead holder at the same time:
+//! exactly one of them must get it.
+//!
+//! The processes are this test binary, run again with the `ALIVE_LOCK_TEST_ROLE` env variable.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    mem,
+    path::PathBuf,
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use alive_lock_file::{Backend, LockOptions, LockResultWithDrop, LockState};
+
+const ROLE_ENV: &str = "ALIVE_LOCK_TEST_ROLE";
+const DIR_ENV: &str = "ALIVE_LOCK_TEST_DIR";
+const BACKEND_ENV: &str = "ALIVE_LOCK_TEST_BACKEND";
+
+const NAME: &str = "takeover.lock";
+const CONTENDERS: usize = 8;
+const ROUNDS: usize = 10;
+
+#[test]
+fn takeover_file() {
+    takeover("file");
+}
+
+#[cfg(unix)]
+#[test]
+fn takeover_flock() {
+    takeover("flock");
+}
+
+fn takeover(backend: &str) {
+    let dir = env::temp_dir().join(format!(
+        "alive_lock_file_takeover_{backend}_{}",
+        process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    for round in 0..ROUNDS {
+        // a holder that exit without releasing its lock leave a stale lock file behind
+        let status = child("leak", &dir, backend)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(matches!(
+            options(&dir, backend).lock_state(NAME).unwrap(),
+            LockState::Stale(_)
+        ));
+
+        let mut contenders: Vec<Child> = (0..CONTENDERS)
+            .map(|_| {
+                child("contend", &dir, backend)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+
+        // start them all at once, once they are all running
+        let start = SystemTime::now() + Duration::from_millis(50);
+        let start = start.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        for contender in &mut contenders {
+            writeln!(contender.stdin.as_mut().unwrap(), "{}", start.as_nanos()).unwrap();
+        }
+
+        // the winner hold the lock until every contender tried
+        let results: Vec<String> = contenders
+            .iter_mut()
+            .map(|contender| {
+                let stdout = BufReader::new(contender.stdout.as_mut().unwrap());
+                stdout
+                    .lines()
+                    .map(Result::unwrap)
+                    // libtest can print the name of the test on the same line
+                    .find_map(|line| {
+                        line.split_once("result: ")
+                            .map(|(_, result)| result.to_owned())
+                    })
+                    .expect("the contender exited without trying")
+            })
+            .collect();
+
+        for mut contender in contenders {
+            drop(contender.stdin.take());
+            assert!(contender.wait().unwrap().success());
+        }
+
+        let locked = results.iter().filter(|result| *result == "locked").count();
+        assert_eq!(locked, 1, "round {round}: {results:?}");
+        assert!(!options(&dir, backend).is_locked(NAME).unwrap());
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Entry point of the processes spawned by [`takeover`].
+#[test]
+fn takeover_child() {
+    let Ok(role) = env::var(ROLE_ENV) else {
+        return;
+    };
+    let dir = PathBuf::from(env::var(DIR_ENV).unwrap());
+    let options = options(&dir, &env::var(BACKEND_ENV).unwrap());
+
+    match role.as_str() {
+        "leak" => {
+            let LockResultWithDrop::Locked(lock) = options.try_lock(NAME).unwrap() else {
+                panic!("the lock is not free");
+            };
+            mem::forget(lock);
+        }
+        "contend" => {
+            let mut stdin = std::io::stdin().lock();
+            let mut start = String::new();
+            stdin.read_line(&mut start).unwrap();
+            let start =
+                SystemTime::UNIX_EPOCH + Duration::from_nanos(start.trim().parse().unwrap());
+
+            if let Ok(delay) = start.duration_since(SystemTime::now()) {
+                thread::sleep(delay);
+            }
+
+            let lock = options.try_lock(NAME).unwrap();
+            let result = match &lock {
+                LockResultWithDrop::Locked(_) => "locked",
+                LockResultWithDrop::AlreadyLocked => "busy",
+            };
+            println!("result: {result}");
+            std::io::stdout().flush().unwrap();
+
+            // wait for the other contenders
+            stdin.read_line(&mut String::new()).unwrap();
+        }
+        role => panic!("unknown role {role}"),
+    }
+}
+
+fn options(dir: &PathBuf, backend: &str) -> LockOptions {
+    let mut options = LockOptions::new();
+    options.dir(dir);
+    match backend {
+        "file" => options.backend(Backend::File),
+        #[cfg(unix)]
+        "flock" => options.backend(Backend::Flock),
+        backend => panic!("unknown backend {backend}"),
+    };
+    options
+}
+
+fn child(role: &str, dir: &PathBuf, backend: &str) -> Command {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command
+        .args([
+            "--exact",
+            "takeover_child",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(ROLE_ENV, role)
+        .env(DIR_ENV, dir)
+        .env(BACKEND_ENV, backend);
+    command
+}